@@ -0,0 +1,156 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Multiplicative decrease applied to an overloaded limit.
+const BACKOFF: f64 = 0.75;
+/// Smallest number of samples a decision is based on.
+const MIN_WINDOW: usize = 5;
+
+/// AIMD concurrency limit driven by task latency and failures.
+///
+/// Each window of completed tasks either raises the limit by one, when tasks
+/// were fast, healthy and the limit was actually used, or cuts it by a
+/// quarter when latency exceeded the target or too many of them failed.
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    min: usize,
+    max: usize,
+    target_latency: Duration,
+    /// Share of failed tasks in a window above which the limit is lowered
+    max_error_rate: f64,
+    window: Mutex<Window>
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    limit: usize,
+    samples: usize,
+    failures: usize,
+    latency: Duration,
+    saturated: bool
+}
+
+impl AdaptiveLimit {
+    pub fn new(
+        min: usize,
+        max: usize,
+        initial: usize,
+        target_latency: Duration,
+        max_error_rate: f64
+    ) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        let window = Window { limit: initial.clamp(min, max), ..Default::default() };
+        Self { min, max, target_latency, max_error_rate, window: Mutex::new(window) }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.window.lock().unwrap().limit
+    }
+
+    /// Account one finished task, returns the new limit when it changed.
+    ///
+    /// `saturated` tells whether the limit was reached while it ran, the
+    /// limit only grows when it is the bottleneck.
+    pub fn record(
+        &self,
+        latency: Duration,
+        failed: bool,
+        saturated: bool
+    ) -> Option<usize> {
+        let mut window = self.window.lock().unwrap();
+        window.samples += 1;
+        window.failures += usize::from(failed);
+        window.latency += latency;
+        window.saturated |= saturated;
+
+        if window.samples < window.limit.max(MIN_WINDOW) {
+            return None;
+        }
+
+        let average = window.latency / window.samples as u32;
+        let error_rate = window.failures as f64 / window.samples as f64;
+        let previous = window.limit;
+
+        if average > self.target_latency || error_rate > self.max_error_rate {
+            window.limit = ((previous as f64 * BACKOFF) as usize).max(self.min);
+        } else if window.saturated {
+            window.limit = (previous + 1).min(self.max);
+        }
+
+        let limit = window.limit;
+        *window = Window { limit, ..Default::default() };
+
+        (limit != previous).then(|| {
+            log::debug!(
+                "🎚️  Worker limit {previous} -> {limit} (avg {average:.2?}, {:.0}% failed)",
+                error_rate * 100.0
+            );
+            limit
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: Duration = Duration::from_millis(100);
+    const FAST: Duration = Duration::from_millis(10);
+    const SLOW: Duration = Duration::from_millis(500);
+
+    /// Records `count` samples, returns the last decision.
+    fn record_n(
+        limit: &AdaptiveLimit,
+        count: usize,
+        latency: Duration,
+        failed: bool,
+        saturated: bool
+    ) -> Option<usize> {
+        (0..count).map(|_| limit.record(latency, failed, saturated)).last().flatten()
+    }
+
+    #[test]
+    fn increases_when_saturated_and_healthy() {
+        let limit = AdaptiveLimit::new(1, 8, 2, TARGET, 0.5);
+
+        assert_eq!(record_n(&limit, MIN_WINDOW - 1, FAST, false, true), None);
+        assert_eq!(limit.record(FAST, false, true), Some(3));
+        assert_eq!(limit.limit(), 3);
+
+        // An unused limit is not the bottleneck
+        assert_eq!(record_n(&limit, MIN_WINDOW, FAST, false, false), None);
+        assert_eq!(limit.limit(), 3);
+    }
+
+    #[test]
+    fn backs_off_on_latency_or_errors() {
+        let limit = AdaptiveLimit::new(1, 16, 8, TARGET, 0.5);
+        assert_eq!(record_n(&limit, 8, SLOW, false, true), Some(6));
+
+        let limit = AdaptiveLimit::new(1, 16, 4, TARGET, 0.5);
+        assert_eq!(record_n(&limit, 2, FAST, false, true), None);
+        assert_eq!(record_n(&limit, 3, FAST, true, true), Some(3));
+    }
+
+    #[test]
+    fn tolerates_errors_up_to_the_threshold() {
+        let limit = AdaptiveLimit::new(1, 16, 4, TARGET, 0.5);
+        assert_eq!(record_n(&limit, 3, FAST, false, true), None);
+        assert_eq!(record_n(&limit, 2, FAST, true, true), Some(5));
+    }
+
+    #[test]
+    fn clamps_to_min_and_max() {
+        assert_eq!(AdaptiveLimit::new(2, 4, 10, TARGET, 0.5).limit(), 4);
+        assert_eq!(AdaptiveLimit::new(2, 4, 1, TARGET, 0.5).limit(), 2);
+
+        let limit = AdaptiveLimit::new(2, 4, 4, TARGET, 0.5);
+        assert_eq!(record_n(&limit, MIN_WINDOW, FAST, false, true), None);
+        assert_eq!(limit.limit(), 4);
+
+        let limit = AdaptiveLimit::new(2, 4, 2, TARGET, 0.5);
+        assert_eq!(record_n(&limit, MIN_WINDOW, SLOW, false, true), None);
+        assert_eq!(limit.limit(), 2);
+    }
+}
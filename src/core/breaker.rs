@@ -0,0 +1,267 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::core::stats::{Counter, Stats};
+
+/// Number of recent results the failure ratio is computed over.
+const RATIO_WINDOW: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitState {
+    /// Jobs run normally.
+    Closed,
+    /// Jobs are short-circuited until the cooldown is over.
+    Open,
+    /// A single probe job runs, its result closes or reopens the circuit.
+    HalfOpen
+}
+
+/// Trip conditions shared by every circuit.
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerPolicy {
+    /// Open after this many failures in a row.
+    pub failures: Option<u32>,
+    /// Open when the share of failures over the last results exceeds it.
+    pub ratio: Option<f64>,
+    /// Time an open circuit waits before letting a probe through.
+    pub cooldown: Duration
+}
+
+/// Admission handed out by [`Breakers::allow`], its result is recorded
+/// with it so the probe of a half-open circuit is told apart from jobs
+/// that started before the circuit opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    probe: Option<u64>
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive: u32,
+    recent: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    /// Token of the probe in flight while half-open
+    probe: Option<u64>
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive: 0,
+            recent: VecDeque::with_capacity(RATIO_WINDOW),
+            opened_at: None,
+            probe: None
+        }
+    }
+}
+
+/// One circuit breaker per event type, disabled without a policy.
+#[derive(Debug, Default)]
+pub struct Breakers {
+    policy: Option<BreakerPolicy>,
+    circuits: Mutex<HashMap<String, Circuit>>,
+    next_probe: AtomicU64,
+    stats: Stats
+}
+
+impl Breakers {
+    pub fn new(
+        policy: Option<BreakerPolicy>,
+        stats: Stats
+    ) -> Self {
+        Self { policy, circuits: Mutex::default(), next_probe: AtomicU64::new(1), stats }
+    }
+
+    /// Whether a job of `event` may run now, `None` while its circuit is
+    /// open. Moves an open circuit past its cooldown to half-open and lets
+    /// that job through as the probe.
+    pub fn allow(
+        &self,
+        event: &str
+    ) -> Option<Pass> {
+        const PASS: Option<Pass> = Some(Pass { probe: None });
+
+        let Some(policy) = self.policy else {
+            return PASS;
+        };
+
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(event) else {
+            return PASS;
+        };
+
+        match circuit.state {
+            CircuitState::Closed => PASS,
+            CircuitState::Open
+                if circuit.opened_at.is_none_or(|at| at.elapsed() < policy.cooldown) =>
+            {
+                None
+            }
+            CircuitState::HalfOpen if circuit.probe.is_some() => None,
+            CircuitState::Open | CircuitState::HalfOpen => {
+                if circuit.state == CircuitState::Open {
+                    log::warn!("🔌 Circuit `{event}` half-open, probing");
+                    circuit.state = CircuitState::HalfOpen;
+                }
+                let probe = self.next_probe.fetch_add(1, Ordering::Relaxed);
+                circuit.probe = Some(probe);
+                Some(Pass { probe: Some(probe) })
+            }
+        }
+    }
+
+    /// Account the result of a job admitted with `pass`, `None` for outcomes
+    /// that say nothing about the downstream health (canceled, delayed).
+    ///
+    /// Only the probe decides on a half-open circuit, results of jobs that
+    /// started before the circuit opened are ignored until it closes.
+    pub fn record(
+        &self,
+        event: &str,
+        pass: Pass,
+        success: Option<bool>
+    ) {
+        let Some(policy) = self.policy else {
+            return;
+        };
+
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(event.to_string()).or_default();
+
+        let is_probe = pass.probe.is_some() && pass.probe == circuit.probe;
+        if circuit.state != CircuitState::Closed && !is_probe {
+            return;
+        }
+
+        let Some(success) = success else {
+            // The probe did not run to completion, let the next job probe
+            circuit.probe = None;
+            return;
+        };
+
+        if circuit.recent.len() == RATIO_WINDOW {
+            circuit.recent.pop_front();
+        }
+        circuit.recent.push_back(success);
+        circuit.consecutive = if success { 0 } else { circuit.consecutive + 1 };
+
+        match (circuit.state, success) {
+            (CircuitState::HalfOpen, true) => {
+                log::warn!("🔌 Circuit `{event}` closed");
+                *circuit = Circuit::default();
+            }
+            (CircuitState::HalfOpen, false) => Self::open(event, circuit),
+            (CircuitState::Closed, false) if Self::tripped(&policy, circuit) => {
+                Self::open(event, circuit)
+            }
+            _ => ()
+        }
+
+        let open = circuits.values().filter(|c| c.state != CircuitState::Closed).count();
+        self.stats.set(Counter::BreakerOpen, open);
+    }
+
+    /// State of every circuit seen so far.
+    pub fn states(&self) -> HashMap<String, CircuitState> {
+        let circuits = self.circuits.lock().unwrap();
+        circuits.iter().map(|(event, circuit)| (event.clone(), circuit.state)).collect()
+    }
+
+    fn tripped(
+        policy: &BreakerPolicy,
+        circuit: &Circuit
+    ) -> bool {
+        let failures = policy.failures.is_some_and(|max| circuit.consecutive >= max);
+        let ratio = policy.ratio.is_some_and(|max| {
+            let failed = circuit.recent.iter().filter(|success| !**success).count();
+            circuit.recent.len() == RATIO_WINDOW && failed as f64 / RATIO_WINDOW as f64 > max
+        });
+        failures || ratio
+    }
+
+    fn open(
+        event: &str,
+        circuit: &mut Circuit
+    ) {
+        log::error!("🔌 Circuit `{event}` open after {} failure(s) in a row", circuit.consecutive);
+        circuit.state = CircuitState::Open;
+        circuit.opened_at = Some(Instant::now());
+        circuit.probe = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT: &str = "env.updated";
+
+    /// Opens after two failures in a row, probes right away.
+    fn breakers() -> Breakers {
+        let policy = BreakerPolicy { failures: Some(2), ratio: None, cooldown: Duration::ZERO };
+        Breakers::new(Some(policy), Stats::default())
+    }
+
+    fn state(breakers: &Breakers) -> CircuitState {
+        breakers.states().get(EVENT).copied().unwrap_or(CircuitState::Closed)
+    }
+
+    /// Trip the circuit, returns the pass of a job started before it opened.
+    fn trip(breakers: &Breakers) -> Pass {
+        let late = breakers.allow(EVENT).unwrap();
+        for _ in 0..2 {
+            let pass = breakers.allow(EVENT).unwrap();
+            breakers.record(EVENT, pass, Some(false));
+        }
+        assert_eq!(state(breakers), CircuitState::Open);
+        late
+    }
+
+    #[test]
+    fn probe_result_closes_the_circuit() {
+        let breakers = breakers();
+        trip(&breakers);
+
+        let probe = breakers.allow(EVENT).unwrap();
+        assert_eq!(state(&breakers), CircuitState::HalfOpen);
+        assert!(breakers.allow(EVENT).is_none());
+
+        breakers.record(EVENT, probe, Some(true));
+        assert_eq!(state(&breakers), CircuitState::Closed);
+    }
+
+    #[test]
+    fn late_success_does_not_close_the_circuit() {
+        let breakers = breakers();
+        let late = trip(&breakers);
+
+        let probe = breakers.allow(EVENT).unwrap();
+        breakers.record(EVENT, late, Some(true));
+        assert_eq!(state(&breakers), CircuitState::HalfOpen);
+
+        breakers.record(EVENT, probe, Some(false));
+        assert_eq!(state(&breakers), CircuitState::Open);
+    }
+
+    #[test]
+    fn late_release_does_not_admit_a_second_probe() {
+        let breakers = breakers();
+        let late = trip(&breakers);
+
+        let probe = breakers.allow(EVENT).unwrap();
+        breakers.record(EVENT, late, None);
+        assert!(breakers.allow(EVENT).is_none());
+
+        // The probe itself not running lets the next job probe
+        breakers.record(EVENT, probe, None);
+        assert!(breakers.allow(EVENT).is_some());
+    }
+}
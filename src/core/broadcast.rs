@@ -1,3 +1,5 @@
+use std::sync::Mutex;
+
 use broadcast::{Receiver, Sender, channel};
 use tokio::sync::broadcast;
 
@@ -7,13 +9,16 @@ use crate::core::stats::Counter;
 use crate::{State, increment};
 
 pub struct BroadcastManager {
-    sender: Sender<Command>
+    sender: Sender<Command>,
+    capacity: usize,
+    /// Keeps the room check and the send of one sender together
+    sending: Mutex<()>
 }
 
 impl BroadcastManager {
-    pub fn new() -> Self {
-        let (sender, _) = channel(100);
-        Self { sender }
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = channel(capacity);
+        Self { sender, capacity, sending: Mutex::new(()) }
     }
 
     pub fn subscribe(&self) -> Receiver<Command> {
@@ -25,15 +30,33 @@ impl BroadcastManager {
         drop(self.sender.clone());
     }
 
-    //
-    pub fn sender(&self) -> Sender<Command> {
-        self.sender.clone()
+    /// Number of commands not yet received by the dispatcher.
+    pub fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// A full buffer would make the dispatcher lag and lose the oldest command.
+    pub fn is_full(&self) -> bool {
+        self.sender.len() >= self.capacity
+    }
+
+    /// Send unless the buffer is full, the command is handed back then.
+    pub fn try_send(
+        &self,
+        command: Command
+    ) -> Result<(), Box<Command>> {
+        let _sending = self.sending.lock().unwrap();
+        if self.is_full() {
+            return Err(Box::new(command));
+        }
+        let _ = self.sender.send(command);
+        Ok(())
     }
 }
 
 impl Default for BroadcastManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(100)
     }
 }
 
@@ -42,15 +65,15 @@ impl State {
         &self,
         command: Command
     ) -> Result<(), Error> {
-        if !self.is_shutting_down() {
-            let _ = self
-                .broadcast
-                .sender()
-                .send(command.clone())
-                .map_err(|_| Error::Internal("Error sending command".to_string()));
-        } else {
-            increment!(Counter::Rejected);
+        let Command::Run(job) = &command;
+        if self.is_shutting_down() {
+            increment!(self.stats, Counter::Rejected, &job.labels());
             log::warn!("⛔ Cannot send command, shutdown is in progress");
+        } else if let Err(command) = self.broadcast.try_send(command) {
+            let Command::Run(job) = *command;
+            // Spill the new command rather than letting the dispatcher lag
+            increment!(self.stats, Counter::Rejected, &job.labels());
+            log::warn!("⛔ Command buffer full ({} queued), command spilled", self.broadcast.len());
         }
         Ok(())
     }
@@ -1,5 +1,48 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::Span;
+
+use crate::core::stats::Labels;
+
 #[derive(Clone, Debug)]
 pub enum Command {
     // Shutdown,
-    Run
+    Run(Job)
+}
+
+/// Job extracted from a Redis message, carrying its originating context.
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub id: String,
+    pub event: String,
+    pub channel: String,
+    /// Message `data`, kept so the job can be checkpointed and resumed
+    pub data: Value,
+    /// Execution deadline set by the message, wins over the configured ones
+    pub timeout: Option<Duration>,
+    /// 1 for a fresh message, incremented each time the job is resumed
+    pub attempt: u32,
+    /// Set once the job took its rate limit token while parked, it is not
+    /// limited again when re-sent
+    pub rate_token: bool,
+    /// Span opened on message receipt, every log line of the task nests under
+    /// it
+    pub span: Span
+}
+
+impl Job {
+    /// Stats breakdown the job is counted under.
+    pub fn labels(&self) -> Labels {
+        Labels::new(&self.event, &self.channel)
+    }
+}
+
+impl Command {
+    pub fn span(&self) -> &Span {
+        match self {
+            Command::Run(job) => &job.span
+        }
+    }
 }
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::core::Job;
+use crate::core::handle::Watcher;
+use crate::ctx::SharedState;
+
+/// Returned by [`JobContext::checkpoint`] once the job should stop.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("job interrupted by shutdown")]
+pub struct Interrupted;
+
+/// Why a job was asked to stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// The service is shutting down.
+    Shutdown,
+    /// This task alone was canceled by id.
+    Request
+}
+
+/// Execution context handed to every task.
+///
+/// The token is a child of `State::shutdown_token`, it is canceled as soon as
+/// a shutdown starts. Jobs are expected to wind down before
+/// [`JobContext::grace_deadline`], past it they are dropped.
+#[allow(unused)]
+pub struct JobContext {
+    task_id: u32,
+    attempt: u32,
+    token: CancellationToken,
+    abort: CancellationToken,
+    watcher: Watcher,
+    state: SharedState
+}
+
+#[allow(unused)]
+impl JobContext {
+    /// Build the context of `job` and list it in the task registry.
+    pub(crate) fn new(
+        task_id: u32,
+        job: &Job,
+        mut watcher: Watcher,
+        state: SharedState
+    ) -> Self {
+        let token = state.shutdown_token().child_token();
+        let abort = CancellationToken::new();
+        watcher.register(
+            task_id,
+            job.event.clone(),
+            job.id.clone(),
+            job.attempt,
+            token.clone(),
+            abort.clone()
+        );
+        Self { task_id, attempt: job.attempt, token, abort, watcher, state }
+    }
+
+    pub fn task_id(&self) -> u32 {
+        self.task_id
+    }
+
+    /// 1 for a fresh job, incremented each time it is resumed.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Resolves once the job is asked to stop.
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Resolves once this task alone is canceled, the job should return
+    /// without finishing even while draining.
+    pub async fn aborted(&self) {
+        self.abort.cancelled().await
+    }
+
+    /// Why the job was asked to stop, `None` while it may keep running.
+    pub fn cancel_reason(&self) -> Option<CancelReason> {
+        if self.abort.is_cancelled() {
+            Some(CancelReason::Request)
+        } else if self.token.is_cancelled() {
+            Some(CancelReason::Shutdown)
+        } else {
+            None
+        }
+    }
+
+    /// Resolves when the grace period ran out or shutdown was forced, the
+    /// job is about to be dropped.
+    pub async fn forced(&self) {
+        self.watcher.wait_shutdown().await
+    }
+
+    /// Describe what the job is doing, shown in the task registry.
+    pub fn set_phase(
+        &self,
+        phase: &str
+    ) {
+        self.watcher.set_phase(phase);
+    }
+
+    /// Point in time the running shutdown stops waiting for this job, `None`
+    /// while not shutting down or without grace limit.
+    pub fn grace_deadline(&self) -> Option<Instant> {
+        self.state.grace_deadline()
+    }
+
+    /// Time left until [`JobContext::grace_deadline`].
+    pub fn remaining_grace(&self) -> Option<Duration> {
+        self.grace_deadline().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Safe point for long-running jobs, `Err` means stop here and return.
+    pub fn checkpoint(&self) -> Result<(), Interrupted> {
+        if self.token.is_cancelled() {
+            log::debug!("🫡 Task #{} stopping at checkpoint", self.task_id);
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+}
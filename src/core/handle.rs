@@ -1,52 +1,183 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
 
-use tokio::sync::Notify;
-use tokio::time::sleep;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
 
+use crate::core::adaptive::AdaptiveLimit;
 use crate::core::notify::NotifyOnce;
+use crate::core::registry::{Registry, TaskInfo};
+use crate::core::stats::{Counter, STATS, Stats};
 
-#[derive(Clone, Debug, Default)]
+/// Permits handed out when no limit is set.
+const UNLIMITED: usize = Semaphore::MAX_PERMITS;
+
+#[derive(Clone, Debug)]
 pub struct Handle {
     inner: Arc<Inner>
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct Inner {
     graceful: NotifyOnce,
     shutdown: NotifyOnce,
-    released: Notify,
+    resumed: Notify,
+    /// Fair FIFO queue of acquisitions, one permit per watcher
+    permits: Arc<Semaphore>,
+    /// Permits to drop on release after the limit was lowered below the
+    /// running count
+    debt: AtomicUsize,
     count: AtomicUsize,
+    waiting: AtomicUsize,
+    max_waiting: Option<usize>,
     all_done: NotifyOnce,
     grace_period: Mutex<Option<Duration>>,
-    max_count: Option<usize>
+    max_count: Mutex<Option<usize>>,
+    paused: AtomicBool,
+    registry: Registry,
+    adaptive: OnceLock<AdaptiveLimit>,
+    stats: Stats
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Service shutting down")]
-    ShuttingDown // GracefulShutdown(Duration),
+    ShuttingDown, // GracefulShutdown(Duration),
+
+    #[error("Too many acquisitions waiting: {0}")]
+    Saturated(usize),
+
+    #[error("No watcher available within {0:?}")]
+    Timeout(Duration)
 }
 
 impl Handle {
     /// Create a new handle.
-    pub fn new(max_count: Option<usize>) -> Self {
-        if let Some(max_count) = max_count {
-            let inner = Inner { max_count: Some(max_count), ..Default::default() };
-            Handle { inner: Arc::new(inner) }
+    ///
+    /// `max_count: None` means unlimited watchers, `max_waiting: None` an
+    /// unbounded queue of acquisitions.
+    pub fn new(
+        max_count: Option<usize>,
+        max_waiting: Option<usize>,
+        stats: Stats
+    ) -> Self {
+        let inner = Inner {
+            graceful: NotifyOnce::default(),
+            shutdown: NotifyOnce::default(),
+            resumed: Notify::new(),
+            permits: Arc::new(Semaphore::new(max_count.unwrap_or(UNLIMITED))),
+            debt: AtomicUsize::new(0),
+            count: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            max_waiting,
+            all_done: NotifyOnce::default(),
+            grace_period: Mutex::new(None),
+            max_count: Mutex::new(max_count),
+            paused: AtomicBool::new(false),
+            registry: Registry::default(),
+            adaptive: OnceLock::new(),
+            stats
+        };
+        inner.stats.set(Counter::Limit, max_count.unwrap_or(0));
+        Handle { inner: Arc::new(inner) }
+    }
+
+    /// Returns the current grace period duration (if any).
+    pub fn grace_period(&self) -> Option<Duration> {
+        *self.inner.grace_period.lock().unwrap()
+    }
+
+    /// Returns the current concurrency limit, `None` means unlimited.
+    pub fn max_count(&self) -> Option<usize> {
+        *self.inner.max_count.lock().unwrap()
+    }
+
+    /// Change the concurrency limit at runtime.
+    ///
+    /// Lowering the limit never interrupts running watchers, it only delays
+    /// new ones until enough of them are released.
+    pub fn set_max_count(
+        &self,
+        max_count: Option<usize>
+    ) {
+        let mut current = self.inner.max_count.lock().unwrap();
+        let from = current.unwrap_or(UNLIMITED);
+        let to = max_count.unwrap_or(UNLIMITED);
+        *current = max_count;
+        self.inner.stats.set(Counter::Limit, max_count.unwrap_or(0));
+
+        let permits = &self.inner.permits;
+        if to > from {
+            // Pay back permits still owed by running watchers first
+            let grow = to - from;
+            let settled = self
+                .inner
+                .debt
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |debt| {
+                    Some(debt.saturating_sub(grow))
+                })
+                .unwrap_or_default()
+                .min(grow);
+            permits.add_permits(grow - settled);
         } else {
-            Handle::default()
+            let shrink = from - to;
+            let forgotten = permits.forget_permits(shrink);
+            self.inner.debt.fetch_add(shrink - forgotten, Ordering::SeqCst);
+        }
+    }
+
+    /// Let `limiter` drive the worker limit from now on.
+    pub fn set_adaptive(
+        &self,
+        limiter: AdaptiveLimit
+    ) {
+        self.set_max_count(Some(limiter.limit()));
+        let _ = self.inner.adaptive.set(limiter);
+    }
+
+    /// Whether the worker limit is driven by an adaptive limiter.
+    pub fn is_adaptive(&self) -> bool {
+        self.inner.adaptive.get().is_some()
+    }
+
+    /// Feed a finished task to the adaptive limiter, if any.
+    pub fn record(
+        &self,
+        latency: Duration,
+        failed: bool
+    ) {
+        let Some(limiter) = self.inner.adaptive.get() else {
+            return;
+        };
+
+        let saturated = self.waiting() > 0
+            || self.max_count().is_some_and(|max_count| self.count() + 1 >= max_count);
+        if let Some(limit) = limiter.record(latency, failed, saturated) {
+            self.set_max_count(Some(limit));
         }
     }
 
-    pub(crate) fn watcher(&self) -> Watcher {
-        Watcher::new(self.clone())
+    /// Number of acquisitions currently queued.
+    pub fn waiting(&self) -> usize {
+        self.inner.waiting.load(Ordering::SeqCst)
     }
 
-    /// Returns the current grace period duration (if any).
-    pub fn grace_period(&self) -> Option<Duration> {
-        *self.inner.grace_period.lock().unwrap()
+    /// Stop handing out watchers, running ones are not affected.
+    pub fn pause(&self) {
+        self.inner.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Hand out watchers again after [`Handle::pause`].
+    pub fn resume(&self) {
+        self.inner.paused.store(false, Ordering::SeqCst);
+
+        self.inner.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
     }
 
     /// Get the number of connections.
@@ -54,8 +185,21 @@ impl Handle {
         self.inner.count.load(Ordering::SeqCst)
     }
 
-    /// Shutdown the server.
-    pub(self) fn shutdown(&self) {
+    /// Tasks currently holding a watcher.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.inner.registry.snapshot()
+    }
+
+    /// Cancel the task `task_id` only, `false` when it is not running.
+    pub fn cancel_task(
+        &self,
+        task_id: u32
+    ) -> bool {
+        self.inner.registry.cancel(task_id)
+    }
+
+    /// Shutdown the server, running watchers are told to stop immediately.
+    pub(crate) fn shutdown(&self) {
         self.inner.shutdown.notify_waiters();
     }
 
@@ -71,7 +215,7 @@ impl Handle {
         self.inner.graceful.notify_waiters();
     }
 
-    pub(self) fn is_shutting_down(&self) -> bool {
+    pub(crate) fn is_shutting_down(&self) -> bool {
         self.inner.shutdown.is_notified()
     }
 
@@ -83,29 +227,99 @@ impl Handle {
         self.inner.graceful.notified().await;
     }
 
-    pub(crate) async fn try_acquire_watcher(&self) -> Result<Watcher, Error> {
+    /// Wait for a watcher, callers are served in arrival order.
+    ///
+    /// While paused callers are held without counting as waiting. Once
+    /// resumed a caller only counts as waiting when no watcher is free right
+    /// away, `backlog` more jobs queued behind it count against `max_waiting`
+    /// too.
+    pub(crate) async fn try_acquire_watcher(
+        &self,
+        backlog: usize
+    ) -> Result<Watcher, Error> {
+        self.wait_resumed().await?;
+        self.acquire(backlog).await
+    }
+
+    /// Like [`Handle::try_acquire_watcher`], giving up after `duration`, time
+    /// spent paused does not count.
+    pub(crate) async fn try_acquire_for(
+        &self,
+        backlog: usize,
+        duration: Duration
+    ) -> Result<Watcher, Error> {
+        self.wait_resumed().await?;
+        timeout(duration, self.acquire(backlog)).await.unwrap_or(Err(Error::Timeout(duration)))
+    }
+
+    async fn acquire(
+        &self,
+        backlog: usize
+    ) -> Result<Watcher, Error> {
+        if let Ok(permit) = self.inner.permits.clone().try_acquire_owned() {
+            return Ok(Watcher::new(self.clone(), permit));
+        }
+
+        let _waiting = self.enter_waiting(backlog)?;
+
+        loop {
+            self.wait_resumed().await?;
+
+            let permit = tokio::select! {
+                permit = self.inner.permits.clone().acquire_owned() => {
+                    permit.expect("watcher semaphore is never closed")
+                }
+                _ = self.inner.graceful.notified() => return Err(Error::ShuttingDown)
+            };
+
+            // Paused while queued, give the permit back and wait for resume
+            if !self.is_paused() {
+                return Ok(Watcher::new(self.clone(), permit));
+            }
+        }
+    }
+
+    /// Hold the caller while paused, `Err` once shutting down.
+    async fn wait_resumed(&self) -> Result<(), Error> {
         loop {
             if self.inner.graceful.is_notified() {
                 return Err(Error::ShuttingDown);
             }
-
-            let count = self.inner.count.load(Ordering::SeqCst);
-
-            if let Some(max_count) = self.inner.max_count
-                && count < max_count
-            {
-                return Ok(self.watcher());
+            if !self.is_paused() {
+                return Ok(());
             }
 
-            // Wait until a connection is freed
-            self.inner.released.notified().await;
-            // Loop again to check if you can enter
+            // Register before checking so a resume in between is not missed
+            let resumed = self.inner.resumed.notified();
+            if self.is_paused() {
+                tokio::select! {
+                    _ = resumed => (),
+                    _ = self.inner.graceful.notified() => ()
+                }
+            }
         }
     }
 
-    pub(crate) async fn wait_all_done(&self) {
+    fn enter_waiting(
+        &self,
+        backlog: usize
+    ) -> Result<Waiting<'_>, Error> {
+        let max_waiting = self.inner.max_waiting;
+        self.inner
+            .waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waiting| match max_waiting {
+                Some(max_waiting) if waiting + backlog >= max_waiting => None,
+                _ => Some(waiting + 1)
+            })
+            .map_err(|waiting| Error::Saturated(waiting + backlog))?;
+        Ok(Waiting(&self.inner.waiting))
+    }
+
+    /// Wait for every watcher to be released, returns `true` when the grace
+    /// period ran out and the remaining watchers were shut down.
+    pub(crate) async fn wait_all_done(&self) -> bool {
         if self.inner.count.load(Ordering::SeqCst) == 0 {
-            return;
+            return false;
         }
 
         let deadline = *self.inner.grace_period.lock().unwrap();
@@ -113,24 +327,92 @@ impl Handle {
         match deadline {
             Some(duration) => tokio::select! {
                 biased;
-                _ = sleep(duration) => self.shutdown(),
-                _ = self.inner.all_done.notified() => (),
+                _ = sleep(duration) => {
+                    self.dump_tasks();
+                    self.shutdown();
+                    true
+                }
+                _ = self.inner.all_done.notified() => false,
             },
-            None => self.inner.all_done.notified().await
+            None => {
+                self.inner.all_done.notified().await;
+                false
+            }
         }
     }
 }
 
+impl Handle {
+    fn dump_tasks(&self) {
+        let tasks = self.tasks();
+        log::warn!("⌛ Grace period expired with {} task(s) still running", tasks.len());
+        for task in tasks {
+            log::warn!(
+                "⌛ Task #{} {} ({}) attempt {} {} for {}ms",
+                task.task_id,
+                task.event,
+                task.message_id,
+                task.attempt,
+                task.phase,
+                task.elapsed_ms
+            );
+        }
+    }
+}
+
+impl Default for Handle {
+    fn default() -> Self {
+        Handle::new(None, None, STATS.clone())
+    }
+}
+
+/// Counts an acquisition as waiting until it resolves.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub(crate) struct Watcher {
-    handle: Handle
+    handle: Handle,
+    permit: Option<OwnedSemaphorePermit>,
+    task_id: Option<u32>
 }
 
 #[allow(unused)]
 impl Watcher {
-    fn new(handle: Handle) -> Self {
+    fn new(
+        handle: Handle,
+        permit: OwnedSemaphorePermit
+    ) -> Self {
         handle.inner.count.fetch_add(1, Ordering::SeqCst);
 
-        Self { handle }
+        Self { handle, permit: Some(permit), task_id: None }
+    }
+
+    /// List the task holding this watcher in the registry until it is dropped.
+    pub(crate) fn register(
+        &mut self,
+        task_id: u32,
+        event: String,
+        message_id: String,
+        attempt: u32,
+        token: CancellationToken,
+        abort: CancellationToken
+    ) {
+        self.handle.inner.registry.insert(task_id, event, message_id, attempt, token, abort);
+        self.task_id = Some(task_id);
+    }
+
+    pub(crate) fn set_phase(
+        &self,
+        phase: &str
+    ) {
+        if let Some(task_id) = self.task_id {
+            self.handle.inner.registry.set_phase(task_id, phase);
+        }
     }
 
     pub(crate) async fn wait_graceful_shutdown(&self) {
@@ -148,18 +430,193 @@ impl Watcher {
 
 impl Drop for Watcher {
     fn drop(&mut self) {
+        if let Some(task_id) = self.task_id {
+            self.handle.inner.registry.remove(task_id);
+        }
+
         let count = self.handle.inner.count.fetch_sub(1, Ordering::SeqCst) - 1;
 
         if count == 0 && self.handle.inner.graceful.is_notified() {
             self.handle.inner.all_done.notify_waiters();
         }
 
-        // watcher not dropped yet.
-        if let Some(max_count) = self.handle.inner.max_count
-            && count < max_count
-        {
-            // Notify waiters that a slot is available
-            self.handle.inner.released.notify_waiters();
+        // Keep the permit when the limit was lowered meanwhile, otherwise
+        // hand it to the next waiter in line
+        if let Some(permit) = self.permit.take() {
+            let owed = self
+                .handle
+                .inner
+                .debt
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |debt| debt.checked_sub(1))
+                .is_ok();
+            if owed {
+                permit.forget();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use super::*;
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    fn handle(
+        max_count: Option<usize>,
+        max_waiting: Option<usize>
+    ) -> Handle {
+        Handle::new(max_count, max_waiting, Stats::default())
+    }
+
+    async fn acquire(handle: &Handle) -> Watcher {
+        handle.try_acquire_for(0, SHORT).await.expect("a free watcher")
+    }
+
+    /// Spawn a waiter and let it reach the semaphore queue.
+    async fn enqueue(
+        handle: &Handle,
+        f: impl FnOnce(Watcher) + Send + 'static
+    ) -> tokio::task::JoinHandle<()> {
+        let waiting = handle.waiting();
+        let clone = handle.clone();
+        let task = tokio::spawn(async move { f(clone.try_acquire_watcher(0).await.unwrap()) });
+        while handle.waiting() == waiting {
+            tokio::task::yield_now().await;
+        }
+        task
+    }
+
+    #[tokio::test]
+    async fn serves_waiters_in_arrival_order() {
+        let handle = handle(Some(1), None);
+        let first = acquire(&handle).await;
+
+        let order = Arc::new(StdMutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let order = order.clone();
+            tasks.push(enqueue(&handle, move |_watcher| order.lock().unwrap().push(i)).await);
+        }
+
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
         }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn saturated_when_too_many_wait() {
+        let handle = handle(Some(1), Some(1));
+        let _running = acquire(&handle).await;
+        let waiter = enqueue(&handle, drop).await;
+
+        assert!(matches!(handle.try_acquire_watcher(0).await, Err(Error::Saturated(1))));
+        waiter.abort();
+    }
+
+    #[tokio::test]
+    async fn backlog_counts_as_waiting() {
+        let handle = handle(Some(1), Some(2));
+        let _running = acquire(&handle).await;
+
+        assert!(matches!(handle.try_acquire_watcher(2).await, Err(Error::Saturated(2))));
+        assert!(matches!(handle.try_acquire_for(1, SHORT).await, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn free_worker_is_never_saturated() {
+        let handle = handle(Some(1), Some(0));
+        let watcher = handle.try_acquire_watcher(10).await;
+        assert!(watcher.is_ok());
+        assert_eq!(handle.waiting(), 0);
+    }
+
+    #[tokio::test]
+    async fn times_out_without_free_worker() {
+        let handle = handle(Some(1), None);
+        let _running = acquire(&handle).await;
+
+        assert!(
+            matches!(handle.try_acquire_for(0, SHORT).await, Err(Error::Timeout(d)) if d == SHORT)
+        );
+        assert_eq!(handle.waiting(), 0);
+    }
+
+    #[tokio::test]
+    async fn lowering_the_limit_waits_for_running_watchers() {
+        let handle = handle(Some(3), None);
+        let mut running =
+            vec![acquire(&handle).await, acquire(&handle).await, acquire(&handle).await];
+
+        handle.set_max_count(Some(1));
+        assert_eq!(handle.count(), 3);
+
+        running.pop();
+        assert!(handle.try_acquire_for(0, SHORT).await.is_err());
+        running.pop();
+        assert!(handle.try_acquire_for(0, SHORT).await.is_err());
+        running.pop();
+
+        let only = acquire(&handle).await;
+        assert!(handle.try_acquire_for(0, SHORT).await.is_err());
+        drop(only);
+    }
+
+    #[tokio::test]
+    async fn raising_the_limit_settles_debt_first() {
+        let handle = handle(Some(3), None);
+        let mut running =
+            vec![acquire(&handle).await, acquire(&handle).await, acquire(&handle).await];
+
+        handle.set_max_count(Some(1));
+        handle.set_max_count(Some(4));
+        running.push(acquire(&handle).await);
+        assert!(handle.try_acquire_for(0, SHORT).await.is_err());
+
+        running.clear();
+        let all: Vec<_> = (0..4).map(|_| handle.try_acquire_watcher(0)).collect();
+        let mut acquired = Vec::new();
+        for acquire in all {
+            acquired.push(acquire.await.unwrap());
+        }
+        assert_eq!(handle.count(), 4);
+        assert!(handle.try_acquire_for(0, SHORT).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unlimited_without_max_count() {
+        let handle = handle(None, Some(0));
+        let watchers: Vec<_> = (0..100).map(|_| handle.try_acquire_watcher(0)).collect();
+        let mut acquired = Vec::new();
+        for acquire in watchers {
+            acquired.push(acquire.await.unwrap());
+        }
+        assert_eq!(handle.count(), 100);
+    }
+
+    #[tokio::test]
+    async fn paused_callers_are_held_not_saturated() {
+        let handle = handle(Some(1), Some(0));
+        handle.pause();
+
+        let clone = handle.clone();
+        let held = tokio::spawn(async move { clone.try_acquire_for(5, SHORT).await.is_ok() });
+        tokio::time::sleep(SHORT * 2).await;
+        assert_eq!(handle.waiting(), 0);
+        assert!(!held.is_finished());
+
+        handle.resume();
+        assert!(held.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn shutting_down_rejects_acquisitions() {
+        let handle = handle(Some(1), None);
+        handle.graceful_shutdown(None);
+        assert!(matches!(handle.try_acquire_watcher(0).await, Err(Error::ShuttingDown)));
     }
 }
@@ -1,9 +1,17 @@
+pub(crate) mod adaptive;
+pub(crate) mod breaker;
 mod broadcast;
 mod command;
+mod context;
 mod error;
 pub(crate) mod handle;
-mod notify;
+pub(crate) mod notify;
+pub(crate) mod ratelimit;
+pub(crate) mod registry;
+pub(crate) mod report;
 pub(crate) mod stats;
 
 pub use broadcast::BroadcastManager;
-pub use command::Command;
+pub use command::{Command, Job};
+#[allow(unused_imports)]
+pub use context::{CancelReason, Interrupted, JobContext};
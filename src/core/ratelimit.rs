@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::core::Job;
+
+/// Retry delay of parked jobs the command buffer had no room for.
+const FULL_BUFFER_BACKOFF: Duration = Duration::from_millis(50);
+
+/// What to do with a job arriving while its route has no token left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RatePolicy {
+    /// Park the job behind the ones of its event until a token is available,
+    /// other events keep going.
+    #[default]
+    Wait,
+    /// Hand the job to the queue runner as delayed.
+    Delay,
+    /// Reject the job.
+    Drop
+}
+
+/// `count` job starts per `period` for one event type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rate {
+    pub event: String,
+    pub count: u32,
+    pub period: Duration
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    /// Tokens added per second
+    refill: f64,
+    last: Instant
+}
+
+impl Bucket {
+    fn new(rate: &Rate) -> Self {
+        let capacity = f64::from(rate.count);
+        Self {
+            capacity,
+            tokens: capacity,
+            refill: capacity / rate.period.as_secs_f64().max(f64::EPSILON),
+            last: Instant::now()
+        }
+    }
+
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill).min(self.capacity);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill))
+        }
+    }
+}
+
+/// Token bucket per event type, events without a rate are never limited.
+#[derive(Debug, Default)]
+pub struct RateLimits {
+    buckets: HashMap<String, Mutex<Bucket>>
+}
+
+impl RateLimits {
+    pub fn new(rates: &[Rate]) -> Self {
+        let buckets =
+            rates.iter().map(|rate| (rate.event.clone(), Mutex::new(Bucket::new(rate)))).collect();
+        Self { buckets }
+    }
+
+    /// Take a token for `event`, `Err` holds the time until the next one.
+    pub fn try_take(
+        &self,
+        event: &str
+    ) -> Result<(), Duration> {
+        match self.buckets.get(event) {
+            Some(bucket) => bucket.lock().unwrap().try_take(),
+            None => Ok(())
+        }
+    }
+}
+
+/// Jobs parked past their rate limit, one bounded FIFO queue per event.
+#[derive(Debug)]
+pub struct ParkedJobs {
+    queues: Mutex<HashMap<String, VecDeque<Job>>>,
+    capacity: usize,
+    parked: Notify
+}
+
+impl ParkedJobs {
+    /// `capacity` jobs at most are parked per event.
+    pub fn new(capacity: usize) -> Self {
+        Self { queues: Mutex::default(), capacity, parked: Notify::new() }
+    }
+
+    /// Whether jobs of `event` are parked, newer ones must queue behind them.
+    pub fn is_parked(
+        &self,
+        event: &str
+    ) -> bool {
+        self.queues.lock().unwrap().contains_key(event)
+    }
+
+    /// Queue `job` behind the parked jobs of its event, handed back when the
+    /// queue is full.
+    pub fn park(
+        &self,
+        job: Job
+    ) -> Result<(), Box<Job>> {
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.entry(job.event.clone()).or_default();
+        if queue.len() >= self.capacity {
+            return Err(Box::new(job));
+        }
+        queue.push_back(job);
+        self.parked.notify_one();
+        Ok(())
+    }
+
+    /// Wait until a job is parked.
+    pub async fn on_parked(&self) {
+        self.parked.notified().await;
+    }
+
+    /// Hand the oldest parked jobs to `send` as long as their event has
+    /// tokens, `send` returns the job when it cannot take it now. Returns the
+    /// time until the next job can go, `None` when nothing is parked.
+    pub fn release(
+        &self,
+        limits: &RateLimits,
+        mut send: impl FnMut(Job) -> Result<(), Box<Job>>
+    ) -> Option<Duration> {
+        let mut queues = self.queues.lock().unwrap();
+        let mut next: Option<Duration> = None;
+
+        for (event, queue) in queues.iter_mut() {
+            while let Some(job) = queue.front_mut() {
+                if !job.rate_token {
+                    match limits.try_take(event) {
+                        Ok(()) => job.rate_token = true,
+                        Err(wait) => {
+                            next = Some(next.map_or(wait, |next| next.min(wait)));
+                            break;
+                        }
+                    }
+                }
+
+                let job = queue.pop_front().expect("front job exists");
+                if let Err(job) = send(job) {
+                    // The job keeps its token and its place
+                    queue.push_front(*job);
+                    next = Some(FULL_BUFFER_BACKOFF);
+                    break;
+                }
+            }
+        }
+
+        queues.retain(|_, queue| !queue.is_empty());
+        next
+    }
+
+    /// Take every parked job out, oldest first within an event.
+    pub fn take_all(&self) -> Vec<Job> {
+        self.queues.lock().unwrap().drain().flat_map(|(_, queue)| queue).collect()
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+/// In-flight task as seen from the outside.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskInfo {
+    pub task_id: u32,
+    pub event: String,
+    pub message_id: String,
+    pub attempt: u32,
+    pub started_at: String,
+    pub elapsed_ms: u64,
+    pub phase: String
+}
+
+#[derive(Debug)]
+struct Entry {
+    event: String,
+    message_id: String,
+    attempt: u32,
+    started_at: SystemTime,
+    started: Instant,
+    phase: String,
+    token: CancellationToken,
+    abort: CancellationToken
+}
+
+/// Tasks holding a watcher, keyed by the dispatcher task id.
+#[derive(Debug, Default)]
+pub struct Registry {
+    tasks: Mutex<BTreeMap<u32, Entry>>
+}
+
+impl Registry {
+    pub fn insert(
+        &self,
+        task_id: u32,
+        event: String,
+        message_id: String,
+        attempt: u32,
+        token: CancellationToken,
+        abort: CancellationToken
+    ) {
+        let entry = Entry {
+            event,
+            message_id,
+            attempt,
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            phase: "running".to_string(),
+            token,
+            abort
+        };
+        self.tasks.lock().unwrap().insert(task_id, entry);
+    }
+
+    pub fn remove(
+        &self,
+        task_id: u32
+    ) {
+        self.tasks.lock().unwrap().remove(&task_id);
+    }
+
+    pub fn set_phase(
+        &self,
+        task_id: u32,
+        phase: &str
+    ) {
+        if let Some(entry) = self.tasks.lock().unwrap().get_mut(&task_id) {
+            entry.phase = phase.to_string();
+        }
+    }
+
+    /// Cancel a single task, `false` when it is not running.
+    pub fn cancel(
+        &self,
+        task_id: u32
+    ) -> bool {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(entry) = tasks.get_mut(&task_id) else {
+            return false;
+        };
+        entry.phase = "canceling".to_string();
+        entry.abort.cancel();
+        entry.token.cancel();
+        true
+    }
+
+    /// Running tasks ordered by task id, oldest first.
+    pub fn snapshot(&self) -> Vec<TaskInfo> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(task_id, entry)| TaskInfo {
+                task_id: *task_id,
+                event: entry.event.clone(),
+                message_id: entry.message_id.clone(),
+                attempt: entry.attempt,
+                started_at: humantime::format_rfc3339_millis(entry.started_at).to_string(),
+                elapsed_ms: entry.started.elapsed().as_millis() as u64,
+                phase: entry.phase.clone()
+            })
+            .collect()
+    }
+}
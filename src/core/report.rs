@@ -0,0 +1,126 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::core::stats::Stats;
+use crate::ctx::Info;
+
+/// What started the shutdown.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum ShutdownTrigger {
+    Signal { name: String },
+    Event { message_id: String },
+    Admin { request_id: Option<String> }
+}
+
+impl fmt::Display for ShutdownTrigger {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match self {
+            ShutdownTrigger::Signal { name } => write!(f, "signal {name}"),
+            ShutdownTrigger::Event { message_id } => write!(f, "event {message_id}"),
+            ShutdownTrigger::Admin { request_id: Some(id) } => write!(f, "admin request {id}"),
+            ShutdownTrigger::Admin { request_id: None } => write!(f, "admin request")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Phase {
+    pub name: &'static str,
+    pub at: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRecord {
+    pub task_id: u32,
+    pub event: String,
+    pub message_id: String
+}
+
+/// Structured record of a drain, written once the service stops.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShutdownReport {
+    pub instance: String,
+    pub hostname: String,
+    pub trigger: Option<ShutdownTrigger>,
+    pub phases: Vec<Phase>,
+    pub drain_elapsed_ms: Option<u64>,
+    pub stats: Value,
+    pub canceled: Vec<TaskRecord>,
+    pub delayed: Vec<TaskRecord>,
+    pub exit_code: u8,
+    pub error: Option<String>
+}
+
+#[derive(Debug, Default)]
+pub struct ShutdownRecorder {
+    report: Mutex<ShutdownReport>,
+    initiated_at: Mutex<Option<Instant>>
+}
+
+impl ShutdownRecorder {
+    /// Record the first trigger only, repeated requests join the running drain.
+    pub fn initiated(
+        &self,
+        trigger: ShutdownTrigger
+    ) {
+        let mut report = self.report.lock().unwrap();
+        if report.trigger.is_none() {
+            report.trigger = Some(trigger);
+            report.phases.push(phase("initiated"));
+            *self.initiated_at.lock().unwrap() = Some(Instant::now());
+        }
+    }
+
+    pub fn phase(
+        &self,
+        name: &'static str
+    ) {
+        self.report.lock().unwrap().phases.push(phase(name));
+    }
+
+    pub fn canceled(
+        &self,
+        task: TaskRecord
+    ) {
+        self.report.lock().unwrap().canceled.push(task);
+    }
+
+    pub fn delayed(
+        &self,
+        task: TaskRecord
+    ) {
+        self.report.lock().unwrap().delayed.push(task);
+    }
+
+    /// Close the report with the final counters and outcome.
+    pub fn finish(
+        &self,
+        info: &Info,
+        stats: &Stats,
+        exit_code: u8,
+        error: Option<String>
+    ) -> ShutdownReport {
+        let mut report = self.report.lock().unwrap();
+        report.phases.push(phase("completed"));
+        report.instance = info.instance_id().to_string();
+        report.hostname = info.get_hostname().to_string();
+        report.drain_elapsed_ms =
+            self.initiated_at.lock().unwrap().map(|at| at.elapsed().as_millis() as u64);
+        report.stats = serde_json::to_value(stats).unwrap_or_default();
+        report.exit_code = exit_code;
+        report.error = error;
+        report.clone()
+    }
+}
+
+fn phase(name: &'static str) -> Phase {
+    Phase { name, at: humantime::format_rfc3339_millis(SystemTime::now()).to_string() }
+}
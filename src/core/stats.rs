@@ -1,11 +1,21 @@
 use once_cell::sync::Lazy;
+
+/// Process-wide default, the binary hands clones of it to its [`State`].
+///
+/// [`State`]: crate::ctx::State
 pub static STATS: Lazy<Stats> = Lazy::new(Stats::new);
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use serde::Serialize;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize)]
+/// Distinct event/channel pairs broken down before new events are folded
+/// into [`OTHER`], unknown events must not grow the stats without bound.
+const MAX_LABEL_SETS: usize = 32;
+const OTHER: &str = "other";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize)]
 pub enum Counter {
     Received,
     Accepted,
@@ -17,7 +27,79 @@ pub enum Counter {
     Delayed,
     Canceled,
     Waiting,
-    Running
+    Running,
+    Resumed,
+    TimedOut,
+    /// Current worker limit, 0 when unlimited
+    Limit,
+    /// Circuits currently open or half-open
+    BreakerOpen,
+    Throttled,
+    /// Tasks canceled by id from the admin channel
+    Aborted,
+    /// Jobs published on the dead-letter channel while their circuit was open
+    DeadLettered
+}
+
+/// Event and channel a counted message belongs to.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Labels {
+    pub event: String,
+    pub channel: String
+}
+
+impl Labels {
+    pub fn new(
+        event: &str,
+        channel: &str
+    ) -> Self {
+        Self { event: event.to_string(), channel: channel.to_string() }
+    }
+}
+
+/// Name of a snapshot value, a counter total or one of its breakdowns.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Metric {
+    pub counter: Counter,
+    pub labels: Option<Labels>
+}
+
+impl fmt::Display for Metric {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match &self.labels {
+            Some(labels) => write!(
+                f,
+                "{:?}{{event=\"{}\",channel=\"{}\"}}",
+                self.counter, labels.event, labels.channel
+            ),
+            None => write!(f, "{:?}", self.counter)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breakdown {
+    values: BTreeMap<(Counter, Labels), usize>,
+    sets: BTreeSet<Labels>
+}
+
+impl Breakdown {
+    fn increment(
+        &mut self,
+        counter: Counter,
+        labels: &Labels
+    ) {
+        let labels = if self.sets.contains(labels) || self.sets.len() < MAX_LABEL_SETS {
+            labels.clone()
+        } else {
+            Labels::new(OTHER, &labels.channel)
+        };
+        self.sets.insert(labels.clone());
+        *self.values.entry((counter, labels)).or_default() += 1;
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -32,7 +114,14 @@ struct Tracker {
     delayed: Arc<AtomicUsize>,
     canceled: Arc<AtomicUsize>,
     waiting: Arc<AtomicUsize>,
-    running: Arc<AtomicUsize>
+    running: Arc<AtomicUsize>,
+    resumed: Arc<AtomicUsize>,
+    timed_out: Arc<AtomicUsize>,
+    limit: Arc<AtomicUsize>,
+    breaker_open: Arc<AtomicUsize>,
+    throttled: Arc<AtomicUsize>,
+    aborted: Arc<AtomicUsize>,
+    dead_lettered: Arc<AtomicUsize>
 }
 
 impl Tracker {
@@ -55,7 +144,14 @@ impl Tracker {
             Counter::Delayed => self.delayed.fetch_add(1, Ordering::SeqCst),
             Counter::Canceled => self.canceled.fetch_add(1, Ordering::SeqCst),
             Counter::Waiting => self.waiting.fetch_add(1, Ordering::SeqCst),
-            Counter::Running => self.running.fetch_add(1, Ordering::SeqCst)
+            Counter::Running => self.running.fetch_add(1, Ordering::SeqCst),
+            Counter::Resumed => self.resumed.fetch_add(1, Ordering::SeqCst),
+            Counter::TimedOut => self.timed_out.fetch_add(1, Ordering::SeqCst),
+            Counter::Limit => self.limit.fetch_add(1, Ordering::SeqCst),
+            Counter::BreakerOpen => self.breaker_open.fetch_add(1, Ordering::SeqCst),
+            Counter::Throttled => self.throttled.fetch_add(1, Ordering::SeqCst),
+            Counter::Aborted => self.aborted.fetch_add(1, Ordering::SeqCst),
+            Counter::DeadLettered => self.dead_lettered.fetch_add(1, Ordering::SeqCst)
         };
     }
 
@@ -74,7 +170,14 @@ impl Tracker {
             Counter::Delayed => self.delayed.fetch_sub(1, Ordering::SeqCst),
             Counter::Canceled => self.canceled.fetch_sub(1, Ordering::SeqCst),
             Counter::Waiting => self.waiting.fetch_sub(1, Ordering::SeqCst),
-            Counter::Running => self.running.fetch_sub(1, Ordering::SeqCst)
+            Counter::Running => self.running.fetch_sub(1, Ordering::SeqCst),
+            Counter::Resumed => self.resumed.fetch_sub(1, Ordering::SeqCst),
+            Counter::TimedOut => self.timed_out.fetch_sub(1, Ordering::SeqCst),
+            Counter::Limit => self.limit.fetch_sub(1, Ordering::SeqCst),
+            Counter::BreakerOpen => self.breaker_open.fetch_sub(1, Ordering::SeqCst),
+            Counter::Throttled => self.throttled.fetch_sub(1, Ordering::SeqCst),
+            Counter::Aborted => self.aborted.fetch_sub(1, Ordering::SeqCst),
+            Counter::DeadLettered => self.dead_lettered.fetch_sub(1, Ordering::SeqCst)
         };
     }
 
@@ -93,7 +196,41 @@ impl Tracker {
             Counter::Delayed => self.delayed.load(Ordering::SeqCst),
             Counter::Canceled => self.canceled.load(Ordering::SeqCst),
             Counter::Waiting => self.waiting.load(Ordering::SeqCst),
-            Counter::Running => self.running.load(Ordering::SeqCst)
+            Counter::Running => self.running.load(Ordering::SeqCst),
+            Counter::Resumed => self.resumed.load(Ordering::SeqCst),
+            Counter::TimedOut => self.timed_out.load(Ordering::SeqCst),
+            Counter::Limit => self.limit.load(Ordering::SeqCst),
+            Counter::BreakerOpen => self.breaker_open.load(Ordering::SeqCst),
+            Counter::Throttled => self.throttled.load(Ordering::SeqCst),
+            Counter::Aborted => self.aborted.load(Ordering::SeqCst),
+            Counter::DeadLettered => self.dead_lettered.load(Ordering::SeqCst)
+        }
+    }
+
+    fn set(
+        &self,
+        counter: Counter,
+        value: usize
+    ) {
+        match counter {
+            Counter::Received => self.received.store(value, Ordering::SeqCst),
+            Counter::Rejected => self.rejected.store(value, Ordering::SeqCst),
+            Counter::Lagged => self.lagged.store(value, Ordering::SeqCst),
+            Counter::Accepted => self.accepted.store(value, Ordering::SeqCst),
+            Counter::Ignored => self.ignored.store(value, Ordering::SeqCst),
+            Counter::Done => self.done.store(value, Ordering::SeqCst),
+            Counter::Failed => self.failed.store(value, Ordering::SeqCst),
+            Counter::Delayed => self.delayed.store(value, Ordering::SeqCst),
+            Counter::Canceled => self.canceled.store(value, Ordering::SeqCst),
+            Counter::Waiting => self.waiting.store(value, Ordering::SeqCst),
+            Counter::Running => self.running.store(value, Ordering::SeqCst),
+            Counter::Resumed => self.resumed.store(value, Ordering::SeqCst),
+            Counter::TimedOut => self.timed_out.store(value, Ordering::SeqCst),
+            Counter::Limit => self.limit.store(value, Ordering::SeqCst),
+            Counter::BreakerOpen => self.breaker_open.store(value, Ordering::SeqCst),
+            Counter::Throttled => self.throttled.store(value, Ordering::SeqCst),
+            Counter::Aborted => self.aborted.store(value, Ordering::SeqCst),
+            Counter::DeadLettered => self.dead_lettered.store(value, Ordering::SeqCst)
         }
     }
 
@@ -110,18 +247,28 @@ impl Tracker {
             (Counter::Canceled, self.canceled.load(Ordering::SeqCst)),
             (Counter::Waiting, self.waiting.load(Ordering::SeqCst)),
             (Counter::Running, self.running.load(Ordering::SeqCst)),
+            (Counter::Resumed, self.resumed.load(Ordering::SeqCst)),
+            (Counter::TimedOut, self.timed_out.load(Ordering::SeqCst)),
+            (Counter::Limit, self.limit.load(Ordering::SeqCst)),
+            (Counter::BreakerOpen, self.breaker_open.load(Ordering::SeqCst)),
+            (Counter::Throttled, self.throttled.load(Ordering::SeqCst)),
+            (Counter::Aborted, self.aborted.load(Ordering::SeqCst)),
+            (Counter::DeadLettered, self.dead_lettered.load(Ordering::SeqCst)),
         ]
     }
 }
 
+/// Set of counters, clones share the same values.
+#[derive(Debug, Clone, Default)]
 pub struct Stats {
-    tracker: Tracker
+    tracker: Tracker,
+    breakdown: Arc<Mutex<Breakdown>>
 }
 
 #[allow(unused)]
 impl Stats {
     pub fn new() -> Self {
-        Stats { tracker: Tracker::new() }
+        Stats { tracker: Tracker::new(), breakdown: Arc::default() }
     }
 
     pub fn increment(
@@ -131,6 +278,16 @@ impl Stats {
         self.tracker.increment(counter);
     }
 
+    /// Increment the total of `counter` and its `labels` breakdown.
+    pub fn increment_labeled(
+        &self,
+        counter: Counter,
+        labels: &Labels
+    ) {
+        self.tracker.increment(counter);
+        self.breakdown.lock().unwrap().increment(counter, labels);
+    }
+
     pub fn decrement(
         &self,
         counter: Counter
@@ -145,8 +302,24 @@ impl Stats {
         self.tracker.get(counter)
     }
 
-    pub fn snapshot(&self) -> Vec<(Counter, usize)> {
-        self.tracker.snapshot()
+    /// Overwrite a gauge like [`Counter::Limit`].
+    pub fn set(
+        &self,
+        counter: Counter,
+        value: usize
+    ) {
+        self.tracker.set(counter, value);
+    }
+
+    /// Every total, followed by the breakdowns grouped by counter.
+    pub fn snapshot(&self) -> Vec<(Metric, usize)> {
+        let totals = self.tracker.snapshot().into_iter();
+        let totals = totals.map(|(counter, value)| (Metric { counter, labels: None }, value));
+        let breakdown = self.breakdown.lock().unwrap();
+        let labeled = breakdown.values.iter().map(|((counter, labels), value)| {
+            (Metric { counter: *counter, labels: Some(labels.clone()) }, *value)
+        });
+        totals.chain(labeled).collect::<Vec<_>>()
     }
 
     pub fn unknown_count(&self) -> usize {
@@ -155,16 +328,22 @@ impl Stats {
         let failed = self.get(Counter::Failed);
         let delayed = self.get(Counter::Delayed);
         let canceled = self.get(Counter::Canceled);
-        accepted.saturating_sub(done + failed + delayed + canceled)
+        let timed_out = self.get(Counter::TimedOut);
+        let aborted = self.get(Counter::Aborted);
+        let dead_lettered = self.get(Counter::DeadLettered);
+        accepted.saturating_sub(
+            done + failed + delayed + canceled + timed_out + aborted + dead_lettered
+        )
     }
 
     pub fn unhandled_count(&self) -> usize {
         let received = self.get(Counter::Received);
+        let resumed = self.get(Counter::Resumed);
         let accepted = self.get(Counter::Accepted);
         let rejected = self.get(Counter::Rejected);
         let lagged = self.get(Counter::Lagged);
         let ignored = self.get(Counter::Ignored);
-        received.saturating_sub(accepted + rejected + ignored + lagged)
+        (received + resumed).saturating_sub(accepted + rejected + ignored + lagged)
     }
 }
 
@@ -176,7 +355,7 @@ impl fmt::Display for Stats {
         f: &mut fmt::Formatter<'_>
     ) -> fmt::Result {
         let parts: Vec<String> =
-            self.snapshot().into_iter().map(|(c, v)| format!("{c:?}:{v}")).collect();
+            self.snapshot().into_iter().map(|(m, v)| format!("{m}:{v}")).collect();
         write!(f, "{}", parts.join(" "))
     }
 }
@@ -194,14 +373,22 @@ impl Serialize for Stats {
         let snapshot = self.snapshot();
         let mut map = serializer.serialize_map(Some(snapshot.len()))?;
         for (key, value) in snapshot {
-            map.serialize_entry(&key, &value)?;
+            map.serialize_entry(&key.to_string(), &value)?;
         }
         map.end()
     }
 }
 
+/// `increment!(stats, counter)`, `increment!(stats, counter, &labels)` to
+/// also break it down, or `increment!(counter)` on the global [`STATS`].
 #[macro_export]
 macro_rules! increment {
+    ($stats:expr, $counter:expr, $labels:expr) => {
+        $stats.increment_labeled($counter, $labels)
+    };
+    ($stats:expr, $counter:expr) => {
+        $stats.increment($counter)
+    };
     ($counter:expr) => {
         $crate::core::stats::STATS.increment($counter)
     };
@@ -209,6 +396,9 @@ macro_rules! increment {
 
 #[macro_export]
 macro_rules! decrement {
+    ($stats:expr, $counter:expr) => {
+        $stats.decrement($counter)
+    };
     ($counter:expr) => {
         $crate::core::stats::STATS.decrement($counter)
     };
@@ -216,7 +406,46 @@ macro_rules! decrement {
 
 #[macro_export]
 macro_rules! get {
+    ($stats:expr, $counter:expr) => {
+        $stats.get($counter)
+    };
     ($counter:expr) => {
         $crate::core::stats::STATS.get($counter)
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Labeled values of `counter`, keyed by event.
+    fn labeled(
+        stats: &Stats,
+        counter: Counter
+    ) -> BTreeMap<String, usize> {
+        stats
+            .snapshot()
+            .into_iter()
+            .filter(|(metric, _)| metric.counter == counter)
+            .filter_map(|(metric, value)| metric.labels.map(|labels| (labels.event, value)))
+            .collect()
+    }
+
+    #[test]
+    fn collapses_label_sets_past_the_limit_into_other() {
+        let stats = Stats::default();
+        let extra = 8;
+        for i in 0..MAX_LABEL_SETS + extra {
+            stats.increment_labeled(Counter::Done, &Labels::new(&format!("event.{i}"), "ev"));
+        }
+        // Sets known before the limit was reached keep their own labels
+        stats.increment_labeled(Counter::Done, &Labels::new("event.0", "ev"));
+
+        let done = labeled(&stats, Counter::Done);
+        assert_eq!(done.len(), MAX_LABEL_SETS + 1);
+        assert_eq!(done[OTHER], extra);
+        assert_eq!(done["event.0"], 2);
+        assert!(!done.contains_key(&format!("event.{MAX_LABEL_SETS}")));
+        assert_eq!(done.values().sum::<usize>(), stats.get(Counter::Done));
+    }
+}
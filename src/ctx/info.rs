@@ -5,7 +5,14 @@ pub struct Info {
     pub app: String,
     pub user: String,
     pub hostname: String,
-    pub work_dir: String
+    pub work_dir: String,
+    /// Identifies this instance, `<app>@<hostname>:<pid>` unless set, only an
+    /// explicit id survives restarts
+    pub instance_id: String,
+    /// Same across restarts, the explicit instance id or `<app>@<hostname>`
+    pub stable_id: String,
+    /// Operator defined labels used for targeting, e.g. `canary`
+    pub tags: Vec<String>
 }
 
 #[allow(unused)]
@@ -25,6 +32,14 @@ impl Info {
     pub(crate) fn my_name(&self) -> &str {
         self.app.as_ref()
     }
+
+    pub fn instance_id(&self) -> &str {
+        self.instance_id.as_ref()
+    }
+
+    pub fn stable_id(&self) -> &str {
+        self.stable_id.as_ref()
+    }
 }
 
 impl Info {
@@ -34,7 +49,23 @@ impl Info {
         work_dir: String
     ) -> Self {
         let app = env!("CARGO_PKG_NAME").to_string();
-        Self { app, user, hostname, work_dir }
+        let stable_id = format!("{app}@{hostname}");
+        // The pid keeps replicas running on the same host apart
+        let instance_id = format!("{stable_id}:{}", std::process::id());
+        Self { app, user, hostname, work_dir, instance_id, stable_id, tags: Vec::new() }
+    }
+
+    pub fn with_identity(
+        mut self,
+        instance_id: Option<String>,
+        tags: Vec<String>
+    ) -> Self {
+        if let Some(instance_id) = instance_id {
+            self.stable_id = instance_id.clone();
+            self.instance_id = instance_id;
+        }
+        self.tags = tags;
+        self
     }
 
     pub fn from_env() -> Result<Self, Error> {
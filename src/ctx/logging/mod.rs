@@ -0,0 +1,157 @@
+mod rotation;
+mod telemetry;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{env, fs, io};
+
+use once_cell::sync::OnceCell;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt};
+
+use self::rotation::{RotatingFile, RotationPolicy};
+pub use self::telemetry::{continue_trace, shutdown};
+use crate::ctx::Options;
+use crate::ctx::options::LogFormat;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid UTF-8 output")]
+    Utf8(#[from] std::string::FromUtf8Error),
+
+    #[error("Path not found {0}")]
+    NotFound(String),
+
+    #[error("Journald unavailable: {0}")]
+    Journald(io::Error),
+
+    #[error("Telemetry error: {0}")]
+    Telemetry(String)
+}
+
+use crate::ctx::utils::is_running_under_systemd;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+static LOG_FILE: OnceCell<Arc<RotatingFile>> = OnceCell::new();
+
+pub async fn init_log(options: &Options) -> Result<(), Error> {
+    let policy = RotationPolicy {
+        rotation: options.log_rotation,
+        max_size: options.log_max_size,
+        keep: options.log_keep,
+        compress: options.log_compress
+    };
+
+    let rotation_configured = policy.is_configured();
+
+    let layer = match options.log_format {
+        Some(LogFormat::Journald) => journald_layer()?,
+        Some(format) => fmt_layer(format, get_log_path()?, policy)?,
+        // Under systemd prefer the journal, keep the log file as a fallback
+        None if is_running_under_systemd() => match journald_layer() {
+            Ok(layer) => layer,
+            Err(_) => fmt_layer(LogFormat::Text, get_log_path()?, policy)?
+        },
+        None => fmt_layer(LogFormat::Text, None, policy)?
+    };
+
+    let mut layers = vec![layer];
+    if let Some(endpoint) = &options.otlp_endpoint {
+        layers.push(telemetry::otel_layer(endpoint)?);
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    if LOG_FILE.get().is_some() {
+        tokio::spawn(reopen_on_signal());
+    } else if rotation_configured {
+        // Rotation only applies to the log file written under systemd
+        log::warn!(
+            "⚠️  --log-rotation, --log-max-size and --log-compress are ignored, not logging to a file"
+        );
+    }
+
+    Ok(())
+}
+
+/// Reopen the log file on SIGUSR1 for external logrotate compatibility.
+async fn reopen_on_signal() {
+    let mut user_signal = match signal(SignalKind::user_defined1()) {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("Failed to create SIGUSR1 handler, log reopen disabled: {e}");
+            return;
+        }
+    };
+
+    while user_signal.recv().await.is_some() {
+        if let Some(file) = LOG_FILE.get() {
+            match file.reopen() {
+                Ok(_) => log::info!("📝 Log file reopened"),
+                Err(e) => log::error!("❌ Log file reopen failed: {e}")
+            }
+        }
+    }
+}
+
+fn get_log_path() -> Result<Option<String>, Error> {
+    if is_running_under_systemd() {
+        let log_dir = env::var("LOGS_DIRECTORY")
+            .or_else(|_| env::var("LOGS_DIR"))
+            .unwrap_or_else(|_| "./storage/logs".to_string());
+
+        fs::create_dir_all(&log_dir)?;
+
+        let log_path = PathBuf::from(&log_dir)
+            .join("app.log")
+            .to_str()
+            .ok_or(Error::NotFound(log_dir))?
+            .to_string();
+
+        Ok(Some(log_path))
+    } else {
+        Ok(None)
+    }
+}
+
+fn fmt_layer(
+    format: LogFormat,
+    log_path: Option<String>,
+    policy: RotationPolicy
+) -> Result<BoxedLayer, Error> {
+    let writer = match log_path {
+        Some(log_path) => {
+            let file = Arc::new(RotatingFile::open(PathBuf::from(log_path), policy)?);
+            let _ = LOG_FILE.set(file.clone());
+            BoxMakeWriter::new(file)
+        }
+        None => BoxMakeWriter::new(io::stdout)
+    };
+
+    let layer = match format {
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(writer)
+            .boxed(),
+        _ => fmt::layer().with_writer(writer).boxed()
+    };
+
+    Ok(layer)
+}
+
+fn journald_layer() -> Result<BoxedLayer, Error> {
+    let layer = tracing_journald::layer().map_err(Error::Journald)?;
+    Ok(layer.boxed())
+}
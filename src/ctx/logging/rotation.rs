@@ -0,0 +1,210 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::ctx::options::LogRotation;
+
+#[derive(Debug, Clone)]
+pub struct RotationPolicy {
+    pub rotation: LogRotation,
+    pub max_size: Option<u64>,
+    pub keep: usize,
+    pub compress: bool
+}
+
+impl RotationPolicy {
+    /// Whether any rotation option differs from the defaults.
+    pub fn is_configured(&self) -> bool {
+        self.rotation != LogRotation::Never || self.max_size.is_some() || self.compress
+    }
+}
+
+/// Log file that rotates itself by size and/or time.
+///
+/// Rotated files are renamed to `<name>.<timestamp>` next to the active file
+/// and optionally gzipped, only the newest `keep` of them are retained.
+pub struct RotatingFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    inner: Mutex<Inner>
+}
+
+struct Inner {
+    file: File,
+    size: u64,
+    period: u64
+}
+
+impl RotatingFile {
+    pub fn open(
+        path: PathBuf,
+        policy: RotationPolicy
+    ) -> io::Result<Self> {
+        let (file, size) = open_append(&path)?;
+        let period = current_period(policy.rotation);
+        Ok(Self { path, policy, inner: Mutex::new(Inner { file, size, period }) })
+    }
+
+    /// Reopen the active file, e.g. after an external logrotate moved it away.
+    pub fn reopen(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.file.flush()?;
+        let (file, size) = open_append(&self.path)?;
+        inner.file = file;
+        inner.size = size;
+        Ok(())
+    }
+
+    fn should_rotate(
+        &self,
+        inner: &Inner,
+        incoming: usize
+    ) -> bool {
+        if self.policy.rotation != LogRotation::Never
+            && current_period(self.policy.rotation) != inner.period
+        {
+            return true;
+        }
+
+        match self.policy.max_size {
+            Some(max_size) => inner.size > 0 && inner.size + incoming as u64 > max_size,
+            None => false
+        }
+    }
+
+    fn rotate(
+        &self,
+        inner: &mut Inner
+    ) -> io::Result<()> {
+        inner.file.flush()?;
+
+        let rotated = self.rotated_path();
+        fs::rename(&self.path, &rotated)?;
+
+        let (file, size) = open_append(&self.path)?;
+        inner.file = file;
+        inner.size = size;
+        inner.period = current_period(self.policy.rotation);
+
+        let path = self.path.clone();
+        let keep = self.policy.keep;
+        if self.policy.compress {
+            // Compression may take a while for big files, keep it off the logging path
+            std::thread::spawn(move || {
+                if let Err(e) = compress(&rotated) {
+                    eprintln!("Log compression failed for {}: {e}", rotated.display());
+                }
+                prune(&path, keep);
+            });
+        } else {
+            prune(&path, keep);
+        }
+
+        Ok(())
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let stamp: String = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+
+        let base = format!("{}.{stamp}", self.path.display());
+        let mut candidate = PathBuf::from(&base);
+        let mut n = 1;
+        while candidate.exists() || gz_path(&candidate).exists() {
+            candidate = PathBuf::from(format!("{base}.{n}"));
+            n += 1;
+        }
+        candidate
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(
+        &mut self,
+        buf: &[u8]
+    ) -> io::Result<usize> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.should_rotate(&inner, buf.len())
+            && let Err(e) = self.rotate(&mut inner)
+        {
+            eprintln!("Log rotation failed for {}: {e}", self.path.display());
+        }
+
+        let written = inner.file.write(buf)?;
+        inner.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn current_period(rotation: LogRotation) -> u64 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    match rotation {
+        LogRotation::Never => 0,
+        LogRotation::Hourly => secs / 3600,
+        LogRotation::Daily => secs / 86400
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.gz", path.display()))
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let mut source = File::open(path)?;
+    let target = File::create(gz_path(path))?;
+    let mut encoder = GzEncoder::new(target, Compression::default());
+    io::copy(&mut source, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Remove the oldest rotated files so that at most `keep` of them remain.
+fn prune(
+    path: &Path,
+    keep: usize
+) {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut rotated: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .collect();
+
+    // Timestamps in the suffix keep lexical order equal to rotation order
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(keep);
+    for old in rotated.into_iter().take(excess) {
+        if let Err(e) = fs::remove_file(&old) {
+            eprintln!("Failed to remove rotated log {}: {e}", old.display());
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::Layer;
+
+use super::{BoxedLayer, Error};
+
+static PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
+
+/// Layer exporting every span over OTLP/HTTP to `endpoint`.
+pub(super) fn otel_layer(endpoint: &str) -> Result<BoxedLayer, Error> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| Error::Telemetry(e.to_string()))?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(env!("CARGO_PKG_NAME")).build())
+        .build();
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let _ = PROVIDER.set(provider);
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+}
+
+/// Continue the publisher's W3C trace when the message carries a `traceparent`.
+pub fn continue_trace(
+    span: &Span,
+    traceparent: Option<&str>,
+    tracestate: Option<&str>
+) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+
+    let mut carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    if let Some(tracestate) = tracestate {
+        carrier.insert("tracestate".to_string(), tracestate.to_string());
+    }
+
+    let parent = TraceContextPropagator::new().extract(&carrier);
+    if let Err(e) = span.set_parent(parent) {
+        log::trace!("Trace context not attached: {e}");
+    }
+}
+
+/// Flush pending spans, call once before the process exits.
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        eprintln!("OpenTelemetry shutdown failed: {e}");
+    }
+}
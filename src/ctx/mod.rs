@@ -3,6 +3,7 @@ mod info;
 pub mod logging;
 pub mod options;
 mod state;
+pub mod systemd;
 pub(crate) mod utils;
 
 pub use error::Error as CtxError;
@@ -1,6 +1,11 @@
+use std::path::PathBuf;
 use std::time::Duration;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+
+use crate::core::breaker::BreakerPolicy;
+use crate::core::ratelimit::{Rate, RatePolicy};
 
 #[derive(Debug, Parser)]
 #[command(name = "subscriber", author, version, about = "high performance event subscriber")]
@@ -11,16 +16,375 @@ pub struct Options {
     #[arg(long, env = "BROADCAST_CHANNEL", help = "redis channel")]
     pub channel: String,
 
-    #[arg(long, short = 'w', help = "max concurrent workers count  none unlimited")]
+    #[arg(long, short = 'w', help = "max concurrent workers count, unlimited when unset")]
     pub workers: Option<usize>,
 
+    #[arg(
+        long = "max-waiting",
+        env = "MAX_WAITING",
+        help = "max jobs queued for a busy worker, buffered commands included, unbounded when unset"
+    )]
+    pub max_waiting: Option<usize>,
+
+    #[arg(
+        long = "acquire-timeout",
+        env = "ACQUIRE_TIMEOUT",
+        value_parser = parse_duration,
+        help = "reject a job not getting a worker within this duration, waits indefinitely when unset"
+    )]
+    pub acquire_timeout: Option<Duration>,
+
+    #[arg(
+        long = "adaptive",
+        env = "ADAPTIVE_WORKERS",
+        help = "adjust the worker limit from task latency and failures, starting at --workers"
+    )]
+    pub adaptive: bool,
+
+    #[arg(
+        long = "min-workers",
+        env = "MIN_WORKERS",
+        default_value_t = 1,
+        help = "lowest adaptive worker limit"
+    )]
+    pub min_workers: usize,
+
+    #[arg(
+        long = "max-workers",
+        env = "MAX_WORKERS",
+        default_value_t = 64,
+        help = "highest adaptive worker limit"
+    )]
+    pub max_workers: usize,
+
+    #[arg(
+        long = "target-latency",
+        env = "TARGET_LATENCY",
+        value_parser = parse_duration,
+        default_value = "1s",
+        help = "average task latency above which the adaptive limit backs off"
+    )]
+    pub target_latency: Duration,
+
+    #[arg(
+        long = "max-error-rate",
+        env = "MAX_ERROR_RATE",
+        value_parser = parse_ratio,
+        default_value_t = 0.5,
+        help = "share of failed tasks in a window above which the adaptive limit backs off"
+    )]
+    pub max_error_rate: f64,
+
+    #[arg(
+        long = "breaker-failures",
+        env = "BREAKER_FAILURES",
+        help = "open an event's circuit after this many failures in a row"
+    )]
+    pub breaker_failures: Option<u32>,
+
+    #[arg(
+        long = "breaker-ratio",
+        env = "BREAKER_RATIO",
+        value_parser = parse_ratio,
+        help = "open an event's circuit when more than this share of its last 20 jobs failed, e.g. 0.5"
+    )]
+    pub breaker_ratio: Option<f64>,
+
+    #[arg(
+        long = "breaker-cooldown",
+        env = "BREAKER_COOLDOWN",
+        value_parser = parse_duration,
+        default_value = "30s",
+        help = "time an open circuit waits before probing again"
+    )]
+    pub breaker_cooldown: Duration,
+
+    #[arg(
+        long = "dead-letter-channel",
+        env = "DEAD_LETTER_CHANNEL",
+        help = "redis channel jobs are published on while their circuit is open, delayed otherwise"
+    )]
+    pub dead_letter_channel: Option<String>,
+
+    #[arg(
+        long = "rate-limit",
+        env = "RATE_LIMITS",
+        value_delimiter = ',',
+        value_parser = parse_rate,
+        help = "max job starts per period for an event, e.g. env.updated=10/1m"
+    )]
+    pub rate_limits: Vec<Rate>,
+
+    #[arg(
+        long = "rate-policy",
+        env = "RATE_POLICY",
+        value_enum,
+        default_value_t = RatePolicy::Wait,
+        help = "what to do with jobs over their rate limit"
+    )]
+    pub rate_policy: RatePolicy,
+
     #[arg(short='t', long= "idle" ,value_parser = parse_duration, help = "idle timeout duration for operations",)]
     pub idle_timeout: Option<Duration>,
 
     #[arg(short = 'g', long = "grace", value_parser = parse_duration, help = "idle timeout duration for operations",)]
-    pub grace_timeout: Option<Duration>
+    pub grace_timeout: Option<Duration>,
+
+    #[arg(
+        long = "task-timeout",
+        env = "TASK_TIMEOUT",
+        value_parser = parse_duration,
+        help = "execution deadline of a task, unlimited when unset"
+    )]
+    pub task_timeout: Option<Duration>,
+
+    #[arg(
+        long = "event-timeout",
+        env = "EVENT_TIMEOUTS",
+        value_delimiter = ',',
+        value_parser = parse_event_timeout,
+        help = "per event execution deadline overriding --task-timeout, e.g. env.updated=30s"
+    )]
+    pub event_timeouts: Vec<(String, Duration)>,
+
+    #[arg(
+        long,
+        env = "COMMAND_BUFFER",
+        default_value_t = 100,
+        help = "commands buffered for the dispatcher, e.g. while paused, extra ones are spilled"
+    )]
+    pub buffer: usize,
+
+    #[arg(
+        long = "instance-id",
+        env = "INSTANCE_ID",
+        required_if_eq("checkpoint", "redis"),
+        help = "stable id of this instance, defaults to <app>@<hostname>:<pid>, required by the redis checkpoint store"
+    )]
+    pub instance_id: Option<String>,
+
+    #[arg(
+        long,
+        env = "TAGS",
+        value_delimiter = ',',
+        help = "comma separated labels this instance can be targeted by"
+    )]
+    pub tags: Vec<String>,
+
+    #[arg(
+        long = "registry-prefix",
+        env = "REGISTRY_PREFIX",
+        default_value = "subscriber",
+        help = "redis key prefix for instance registration and rolling restarts"
+    )]
+    pub registry_prefix: String,
+
+    #[arg(long = "rolling-max-delay", env = "ROLLING_MAX_DELAY", default_value = "60s", value_parser = parse_duration, help = "max time a rolling restart waits for the previous instance to come back, should cover drain and restart")]
+    pub rolling_max_delay: Duration,
+
+    #[arg(
+        long = "admin-channel",
+        env = "ADMIN_CHANNEL",
+        help = "redis channel for runtime admin commands"
+    )]
+    pub admin_channel: Option<String>,
+
+    #[arg(
+        long = "admin-reply-channel",
+        env = "ADMIN_REPLY_CHANNEL",
+        help = "redis channel for admin replies, defaults to <admin-channel>.replies"
+    )]
+    pub admin_reply_channel: Option<String>,
+
+    #[arg(
+        long = "report-path",
+        env = "SHUTDOWN_REPORT_PATH",
+        help = "file the JSON shutdown report is written to"
+    )]
+    pub report_path: Option<PathBuf>,
+
+    #[arg(
+        long = "report-channel",
+        env = "SHUTDOWN_REPORT_CHANNEL",
+        help = "redis channel the JSON shutdown report is published on"
+    )]
+    pub report_channel: Option<String>,
+
+    #[arg(
+        long = "checkpoint",
+        env = "CHECKPOINT",
+        value_enum,
+        help = "store for jobs canceled by a forced shutdown, resumed on next start"
+    )]
+    pub checkpoint: Option<CheckpointStore>,
+
+    #[arg(
+        long = "checkpoint-path",
+        env = "CHECKPOINT_PATH",
+        default_value = "subscriber.checkpoint.json",
+        help = "checkpoint file used by the file store"
+    )]
+    pub checkpoint_path: PathBuf,
+
+    #[arg(
+        long = "log-format",
+        env = "LOG_FORMAT",
+        value_enum,
+        help = "log output format, defaults to journald under systemd and text otherwise"
+    )]
+    pub log_format: Option<LogFormat>,
+
+    #[arg(
+        long = "log-rotation",
+        env = "LOG_ROTATION",
+        value_enum,
+        default_value = "never",
+        help = "time based rotation of the log file"
+    )]
+    pub log_rotation: LogRotation,
+
+    #[arg(long = "log-max-size", env = "LOG_MAX_SIZE", value_parser = parse_size, help = "rotate the log file once it exceeds this size, e.g. 100MB")]
+    pub log_max_size: Option<u64>,
+
+    #[arg(
+        long = "log-keep",
+        env = "LOG_KEEP",
+        default_value_t = 7,
+        help = "number of rotated log files to retain"
+    )]
+    pub log_keep: usize,
+
+    #[arg(long = "log-compress", env = "LOG_COMPRESS", help = "gzip rotated log files")]
+    pub log_compress: bool,
+
+    #[arg(
+        long = "otlp-endpoint",
+        env = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+        help = "OTLP/HTTP traces endpoint, e.g. http://localhost:4318/v1/traces, export disabled when unset"
+    )]
+    pub otlp_endpoint: Option<String>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Structured JSON lines, one event per line.
+    Json,
+    /// Human readable fmt lines.
+    Text,
+    /// Native journald entries with structured fields.
+    Journald
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CheckpointStore {
+    /// JSON array in `--checkpoint-path`.
+    File,
+    /// Redis list keyed by the registry prefix and instance id.
+    Redis
+}
+
+impl Options {
+    /// Checks across options clap cannot express, reported like a parse
+    /// error.
+    pub fn validate(self) -> Result<Self, clap::Error> {
+        if self.min_workers > self.max_workers {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--min-workers {} is above --max-workers {}",
+                    self.min_workers, self.max_workers
+                )
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Circuit breaker policy, `None` when no trip condition is configured.
+    pub fn breaker_policy(&self) -> Option<BreakerPolicy> {
+        if self.breaker_failures.is_none() && self.breaker_ratio.is_none() {
+            return None;
+        }
+        Some(BreakerPolicy {
+            failures: self.breaker_failures,
+            ratio: self.breaker_ratio,
+            cooldown: self.breaker_cooldown
+        })
+    }
+
+    /// Execution deadline for `event`, the per event value wins over
+    /// `task_timeout`.
+    pub fn task_timeout(
+        &self,
+        event: &str
+    ) -> Option<Duration> {
+        self.event_timeouts
+            .iter()
+            .find(|(name, _)| name == event)
+            .map(|(_, timeout)| *timeout)
+            .or(self.task_timeout)
+    }
+
+    pub fn admin_reply_channel(&self) -> Option<String> {
+        self.admin_reply_channel
+            .clone()
+            .or_else(|| self.admin_channel.as_ref().map(|c| format!("{c}.replies")))
+    }
 }
 
 fn parse_duration(s: &str) -> Result<Duration, humantime::DurationError> {
     humantime::parse_duration(s)
 }
+
+fn parse_event_timeout(s: &str) -> Result<(String, Duration), String> {
+    let (event, timeout) =
+        s.split_once('=').ok_or_else(|| format!("expected <event>=<duration>: {s}"))?;
+    let timeout = humantime::parse_duration(timeout.trim()).map_err(|e| e.to_string())?;
+    Ok((event.trim().to_string(), timeout))
+}
+
+fn parse_rate(s: &str) -> Result<Rate, String> {
+    let (event, rate) =
+        s.split_once('=').ok_or_else(|| format!("expected <event>=<count>/<period>: {s}"))?;
+    let (count, period) =
+        rate.split_once('/').ok_or_else(|| format!("expected <count>/<period>: {rate}"))?;
+    let count = count.trim().parse().map_err(|_| format!("invalid rate count: {count}"))?;
+    if count == 0 {
+        return Err(format!("rate count must be greater than 0: {rate}"));
+    }
+    let period = humantime::parse_duration(period.trim()).map_err(|e| e.to_string())?;
+    Ok(Rate { event: event.trim().to_string(), count, period })
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let ratio: f64 = s.trim().parse().map_err(|_| format!("invalid ratio: {s}"))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!("ratio must be between 0 and 1: {s}"));
+    }
+    Ok(ratio)
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid size: {s}"))?;
+    if number == 0 {
+        // A zero limit would rotate on every write
+        return Err("size must be greater than 0".to_string());
+    }
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("invalid size unit: {unit}"))
+    };
+    number.checked_mul(multiplier).ok_or_else(|| format!("size too large: {s}"))
+}
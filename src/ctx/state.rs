@@ -1,11 +1,21 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 
-use clap::Parser;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
 use super::error::Error;
+use super::systemd::Notifier;
 use super::{Info, Options};
-use crate::core::BroadcastManager;
+use crate::core::adaptive::AdaptiveLimit;
+use crate::core::breaker::Breakers;
+use crate::core::handle::Handle;
+use crate::core::notify::NotifyOnce;
+use crate::core::ratelimit::{ParkedJobs, RateLimits};
+use crate::core::report::{ShutdownRecorder, ShutdownTrigger};
+use crate::core::stats::{STATS, Stats};
+use crate::core::{BroadcastManager, Job};
 
 pub type SharedState = Arc<State>;
 
@@ -13,33 +23,122 @@ pub type SharedState = Arc<State>;
 pub struct State {
     pub options: Options,
     pub info: Info,
+    pub stats: Stats,
     shutdown_token: CancellationToken,
-    pub broadcast: BroadcastManager
+    shutdown_started: OnceLock<Instant>,
+    forced: AtomicBool,
+    aborted: NotifyOnce,
+    pub broadcast: BroadcastManager,
+    pub handle: Handle,
+    pub breakers: Breakers,
+    pub rate_limits: RateLimits,
+    pub parked: ParkedJobs,
+    pub report: ShutdownRecorder,
+    canceled_jobs: Mutex<Vec<Job>>,
+    pub systemd: Notifier,
+    connected: AtomicBool,
+    subscribed: NotifyOnce,
+    grace_override: Mutex<Option<Duration>>
 }
 
 impl State {
-    pub fn shared() -> Result<Arc<Self>, Error> {
-        let options = Options::parse();
+    /// State counting into the global [`STATS`].
+    pub fn shared(options: Options) -> Result<Arc<Self>, Error> {
+        Self::with_stats(options, STATS.clone())
+    }
+
+    /// State counting into its own `stats`, isolated from other instances.
+    pub fn with_stats(
+        options: Options,
+        stats: Stats
+    ) -> Result<Arc<Self>, Error> {
         Ok(Arc::new(Self {
+            handle: handle(&options, stats.clone()),
+            breakers: Breakers::new(options.breaker_policy(), stats.clone()),
+            rate_limits: RateLimits::new(&options.rate_limits),
+            parked: ParkedJobs::new(options.buffer),
+            broadcast: BroadcastManager::new(options.buffer),
+            info: Info::from_env()?
+                .with_identity(options.instance_id.clone(), options.tags.clone()),
             options,
-            info: Info::from_env()?,
-            broadcast: BroadcastManager::default(),
-            shutdown_token: CancellationToken::new()
+            stats,
+            shutdown_token: CancellationToken::new(),
+            shutdown_started: OnceLock::new(),
+            forced: AtomicBool::new(false),
+            aborted: NotifyOnce::default(),
+            report: ShutdownRecorder::default(),
+            canceled_jobs: Mutex::new(Vec::new()),
+            systemd: Notifier::from_env(),
+            connected: AtomicBool::new(false),
+            subscribed: NotifyOnce::default(),
+            grace_override: Mutex::new(None)
         }))
     }
 }
 
+/// Worker handle with the adaptive limiter attached when enabled.
+fn handle(
+    options: &Options,
+    stats: Stats
+) -> Handle {
+    let handle = Handle::new(options.workers, options.max_waiting, stats);
+    if options.adaptive {
+        let initial = options.workers.unwrap_or(options.min_workers);
+        handle.set_adaptive(AdaptiveLimit::new(
+            options.min_workers,
+            options.max_workers,
+            initial,
+            options.target_latency,
+            options.max_error_rate
+        ));
+    }
+    handle
+}
+
 impl State {
     pub fn is_shutting_down(&self) -> bool {
         self.shutdown_token.is_cancelled()
     }
 
-    pub fn initiate_shutdown(&self) {
+    pub fn initiate_shutdown(
+        &self,
+        trigger: ShutdownTrigger
+    ) {
+        log::debug!("Shutdown triggered by {trigger}");
+        self.report.initiated(trigger);
+        let _ = self.shutdown_started.set(Instant::now());
         self.shutdown_token.cancel();
         self.broadcast.close();
+        self.systemd.stopping();
         log::warn!("💥 Shutdown initiated. Graceful shutdown in progress...");
     }
 
+    /// Skip the rest of the grace period and cancel running tasks, `false`
+    /// when the drain was already forced.
+    pub fn force_shutdown(&self) -> bool {
+        if self.forced.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.report.phase("forced");
+        self.handle.shutdown();
+        true
+    }
+
+    /// Stop waiting for canceled tasks, the drain ends with whatever was
+    /// accounted so far and the process exits with `ExitStatus::Forced`.
+    pub fn abort_drain(&self) {
+        self.report.phase("aborted");
+        self.aborted.notify_waiters();
+    }
+
+    pub fn is_drain_aborted(&self) -> bool {
+        self.aborted.is_notified()
+    }
+
+    pub fn on_drain_aborted(&self) -> impl Future<Output = ()> + '_ {
+        self.aborted.notified()
+    }
+
     pub fn on_shutdown(&self) -> impl Future<Output = ()> + '_ {
         self.shutdown_token.cancelled()
     }
@@ -47,4 +146,96 @@ impl State {
     pub fn shutdown_token(&self) -> CancellationToken {
         self.shutdown_token.clone()
     }
+
+    /// Grace period of the current shutdown, a per-request override wins over
+    /// `Options::grace_timeout`.
+    pub fn grace_timeout(&self) -> Option<Duration> {
+        self.grace_override.lock().unwrap().or(self.options.grace_timeout)
+    }
+
+    /// End of the grace period of the running shutdown, `None` while not
+    /// shutting down or without grace limit.
+    pub fn grace_deadline(&self) -> Option<Instant> {
+        let started = self.shutdown_started.get()?;
+        self.grace_timeout().map(|grace| *started + grace)
+    }
+
+    /// Override the grace period for the upcoming shutdown, ignored once a
+    /// drain started since the worker handle already runs on the old one.
+    pub fn set_grace_timeout(
+        &self,
+        grace: Duration
+    ) {
+        let grace_fmt = humantime::format_duration(grace);
+        if self.is_shutting_down() {
+            log::warn!(
+                "⏱️  Grace period override {grace_fmt} ignored, shutdown already in progress"
+            );
+            return;
+        }
+        log::warn!("⏱️  Grace period overridden: {grace_fmt}");
+        *self.grace_override.lock().unwrap() = Some(grace);
+    }
+
+    /// Keep a job canceled by a forced shutdown for the checkpoint.
+    pub fn job_canceled(
+        &self,
+        job: Job
+    ) {
+        self.canceled_jobs.lock().unwrap().push(job);
+    }
+
+    pub fn take_canceled_jobs(&self) -> Vec<Job> {
+        std::mem::take(&mut *self.canceled_jobs.lock().unwrap())
+    }
+
+    /// Record that the subscriber is listening, only the first call matters
+    /// for readiness.
+    pub fn mark_subscribed(&self) {
+        self.connected.store(true, Ordering::SeqCst);
+        if !self.subscribed.is_notified() {
+            self.subscribed.notify_waiters();
+            self.systemd.ready();
+        }
+    }
+
+    pub fn mark_disconnected(&self) {
+        self.connected.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether the subscription is currently up.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the subscriber has subscribed for the first time.
+    pub fn on_subscribed(&self) -> impl Future<Output = ()> + '_ {
+        self.subscribed.notified()
+    }
+
+    /// Hold new tasks back while in-flight ones keep running, commands are
+    /// buffered until [`State::resume`].
+    pub fn pause(&self) {
+        if !self.handle.is_paused() {
+            self.handle.pause();
+            log::warn!("⏸️  Dispatching paused, {} task(s) still running", self.handle.count());
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.handle.is_paused() {
+            let buffered = self.broadcast.len();
+            self.handle.resume();
+            log::warn!("▶️  Dispatching resumed, {} command(s) buffered", buffered);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.handle.is_paused()
+    }
+
+    /// Whether the instance accepts new work: neither paused nor shutting down.
+    pub fn is_ready(&self) -> bool {
+        !self.is_paused() && !self.is_shutting_down()
+    }
 }
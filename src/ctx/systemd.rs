@@ -0,0 +1,204 @@
+use std::io;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// `sd_notify` client writing to the `NOTIFY_SOCKET` datagram socket.
+///
+/// Every call is a no-op when no socket is configured, so the service runs
+/// the same outside of a `Type=notify` unit.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    socket: Option<String>,
+    watchdog: Option<Duration>
+}
+
+impl Notifier {
+    /// Socket and watchdog interval as handed over by systemd.
+    pub fn from_env() -> Self {
+        let socket = std::env::var("NOTIFY_SOCKET").ok().filter(|s| !s.is_empty());
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| watchdog_for_us())
+            .and_then(|usec| usec.parse().ok())
+            .filter(|usec| *usec > 0)
+            .map(Duration::from_micros);
+        Self::new(socket, watchdog)
+    }
+
+    /// Notifier for an explicit socket path, `@` prefixed names are abstract.
+    pub fn new(
+        socket: Option<String>,
+        watchdog: Option<Duration>
+    ) -> Self {
+        Self { socket, watchdog }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Ping interval, half the `WatchdogSec=` of the unit.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    pub fn status(
+        &self,
+        status: &str
+    ) {
+        self.notify(&format!("STATUS={status}"));
+    }
+
+    /// Send a raw `KEY=VALUE` state, failures are logged only.
+    pub fn notify(
+        &self,
+        state: &str
+    ) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        if let Err(e) = send(socket, state) {
+            log::warn!("⚠️  sd_notify `{state}` failed: {e}");
+        }
+    }
+}
+
+fn send(
+    socket: &str,
+    state: &str
+) -> io::Result<()> {
+    let address = match socket.strip_prefix('@') {
+        Some(name) => abstract_address(name)?,
+        None => SocketAddr::from_pathname(socket)?
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_address(name: &str) -> io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_address(_name: &str) -> io::Result<SocketAddr> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets are linux only"))
+}
+
+/// `WATCHDOG_PID` restricts the watchdog to one process of the unit.
+fn watchdog_for_us() -> bool {
+    match std::env::var("WATCHDOG_PID") {
+        Ok(pid) => pid.parse() == Ok(std::process::id()),
+        Err(_) => true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Local stand-in for the systemd notify socket.
+    struct Listener {
+        socket: UnixDatagram,
+        path: PathBuf
+    }
+
+    impl Listener {
+        fn bind(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("notify-{}-{name}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let socket = UnixDatagram::bind(&path).unwrap();
+            socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+            Self { socket, path }
+        }
+
+        fn notifier(&self) -> Notifier {
+            Notifier::new(Some(self.path.to_string_lossy().to_string()), None)
+        }
+
+        fn recv(&self) -> String {
+            let mut buf = [0; 256];
+            let len = self.socket.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn sends_exact_datagrams() {
+        let listener = Listener::bind("states");
+        let notifier = listener.notifier();
+        assert!(notifier.is_enabled());
+
+        notifier.ready();
+        assert_eq!(listener.recv(), "READY=1");
+        notifier.watchdog();
+        assert_eq!(listener.recv(), "WATCHDOG=1");
+        notifier.status("ready: 2 running, 0 waiting, 5 done, 0 failed");
+        assert_eq!(listener.recv(), "STATUS=ready: 2 running, 0 waiting, 5 done, 0 failed");
+        notifier.stopping();
+        assert_eq!(listener.recv(), "STOPPING=1");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sends_to_abstract_socket() {
+        use std::os::linux::net::SocketAddrExt;
+
+        let name = format!("subscriber-notify-{}", std::process::id());
+        let address = SocketAddr::from_abstract_name(&name).unwrap();
+        let socket = UnixDatagram::bind_addr(&address).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+
+        Notifier::new(Some(format!("@{name}")), None).ready();
+
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+
+    #[test]
+    fn noop_without_socket() {
+        let notifier = Notifier::new(None, None);
+        assert!(!notifier.is_enabled());
+        assert_eq!(notifier.watchdog_interval(), None);
+        notifier.ready();
+        notifier.watchdog();
+        notifier.status("idle");
+        notifier.stopping();
+    }
+
+    #[test]
+    fn watchdog_interval_is_half_the_timeout() {
+        let notifier = Notifier::new(None, Some(Duration::from_secs(30)));
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn missing_socket_is_not_fatal() {
+        let notifier = Notifier::new(Some("/nonexistent/notify.sock".to_string()), None);
+        notifier.ready();
+    }
+}
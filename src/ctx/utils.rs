@@ -1,3 +1,85 @@
 pub fn is_running_under_systemd() -> bool {
     std::env::var("INVOCATION_ID").is_ok() || std::env::var("JOURNAL_STREAM").is_ok()
 }
+
+/// Shell style glob match supporting `*` (any run) and `?` (any single char).
+pub fn glob_match(
+    pattern: &str,
+    text: &str
+) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Last `*` position in pattern and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_at_start() {
+        assert!(glob_match("*-worker", "api-worker"));
+        assert!(glob_match("*-worker", "-worker"));
+        assert!(!glob_match("*-worker", "api-workers"));
+    }
+
+    #[test]
+    fn star_in_middle() {
+        assert!(glob_match("web-*.prod", "web-1.prod"));
+        assert!(glob_match("web-*.prod", "web-.prod"));
+        assert!(glob_match("a*b*c", "axxbyybzc"));
+        assert!(!glob_match("web-*.prod", "web-1.staging"));
+    }
+
+    #[test]
+    fn star_at_end() {
+        assert!(glob_match("subscriber@*", "subscriber@web-1:4242"));
+        assert!(glob_match("subscriber@*", "subscriber@"));
+        assert!(!glob_match("subscriber@*", "publisher@web-1"));
+    }
+
+    #[test]
+    fn no_wildcard() {
+        assert!(glob_match("subscriber", "subscriber"));
+        assert!(!glob_match("subscriber", "subscriber-2"));
+        assert!(!glob_match("subscriber", "sub"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(glob_match("web-?", "web-1"));
+        assert!(!glob_match("web-?", "web-"));
+        assert!(!glob_match("web-?", "web-12"));
+    }
+
+    #[test]
+    fn empty_pattern_or_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "subscriber"));
+        assert!(!glob_match("subscriber", ""));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", ""));
+        assert!(!glob_match("?", ""));
+    }
+}
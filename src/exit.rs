@@ -0,0 +1,68 @@
+use std::process::ExitCode;
+
+use crate::AppError;
+use crate::core::stats::{Counter, Stats};
+use crate::svc::dispatcher::Error as DispatcherError;
+use crate::svc::pubsub::Error as PubsubError;
+
+/// Process exit codes, stable so supervisors can react to them.
+///
+/// | code | meaning                                                  |
+/// |------|----------------------------------------------------------|
+/// | 0    | clean drain, every accepted task finished                |
+/// | 3    | drained, but tasks were canceled at the grace deadline   |
+/// | 4    | lost tasks or unhandled commands in the final accounting |
+/// | 5    | fatal redis error                                        |
+/// | 78   | configuration error (`EX_CONFIG`)                        |
+/// | 130  | drain aborted by a third shutdown signal                 |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Clean,
+    Canceled,
+    LostTasks,
+    Redis,
+    Config,
+    Forced
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        match self {
+            ExitStatus::Clean => 0,
+            ExitStatus::Canceled => 3,
+            ExitStatus::LostTasks => 4,
+            ExitStatus::Redis => 5,
+            ExitStatus::Config => 78,
+            ExitStatus::Forced => 130
+        }
+    }
+
+    /// Outcome of a drain that completed without errors, tasks canceled by an
+    /// admin request are counted as `Aborted` and do not change it.
+    pub fn from_stats(stats: &Stats) -> Self {
+        if stats.get(Counter::Canceled) > 0 { ExitStatus::Canceled } else { ExitStatus::Clean }
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+impl From<&AppError> for ExitStatus {
+    fn from(error: &AppError) -> Self {
+        match error {
+            AppError::Ctx(_) | AppError::Logging(_) => ExitStatus::Config,
+            AppError::Subscriber(PubsubError::Unhandled(e))
+                if e.kind() == redis::ErrorKind::InvalidClientConfig =>
+            {
+                ExitStatus::Config
+            }
+            AppError::Subscriber(_) => ExitStatus::Redis,
+            AppError::Dispatcher(
+                DispatcherError::UnknownTasks(_) | DispatcherError::UnhandledCommands(_)
+            ) => ExitStatus::LostTasks
+        }
+    }
+}
@@ -1,22 +1,49 @@
 mod core;
 mod ctx;
 mod error;
+mod exit;
 mod svc;
 
+use std::process::ExitCode;
+
+use clap::Parser;
 pub use error::{Error as AppError, Result};
 
-use crate::ctx::{State, logging};
-use crate::svc::{dispatcher, pubsub, shutdown};
+use crate::ctx::{Options, State, logging};
+use crate::exit::ExitStatus;
+use crate::svc::{checkpoint, dispatcher, pubsub, rolling, shutdown, watchdog};
 
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result {
+async fn main() -> ExitCode {
     dotenvy::dotenv().ok();
 
-    logging::init_log().await?;
+    let options = match Options::try_parse().and_then(Options::validate) {
+        Ok(options) => options,
+        Err(err) => {
+            let _ = err.print();
+            // --help and --version end up here as well
+            return if err.use_stderr() { ExitStatus::Config.into() } else { ExitCode::SUCCESS };
+        }
+    };
+
+    match run(options).await {
+        Ok(status) => status.into(),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitStatus::from(&err).into()
+        }
+    }
+}
+
+async fn run(options: Options) -> std::result::Result<ExitStatus, AppError> {
+    logging::init_log(&options).await?;
 
-    let state = State::shared()?;
+    let state = State::shared(options)?;
 
     tokio::spawn(shutdown::listen(state.clone()));
+    tokio::spawn(rolling::register(state.clone()));
+    tokio::spawn(checkpoint::resume(state.clone()));
+    tokio::spawn(watchdog::run(state.clone()));
 
     log::debug!("Options: {:?}", state.options);
 
@@ -24,7 +51,20 @@ async fn main() -> Result {
 
     let dispatcher = dispatcher::run(state.clone());
 
-    match tokio::try_join!(subscriber, dispatcher) {
+    let result = tokio::try_join!(subscriber, dispatcher);
+
+    checkpoint::save(&state).await;
+
+    let status = match &result {
+        _ if state.is_drain_aborted() => ExitStatus::Forced,
+        Ok(_) => ExitStatus::from_stats(&state.stats),
+        Err(err) => ExitStatus::from(err)
+    };
+    shutdown::report(&state, status, result.as_ref().err().map(ToString::to_string)).await;
+
+    logging::shutdown();
+
+    match result {
         Ok((_, _)) => {
             log::info!("❎ Subscriber and Dispatcher completed successfully.");
         }
@@ -34,7 +74,11 @@ async fn main() -> Result {
         }
     }
 
-    log::info!("✅ {} exits successfully! 🎉", state.info.app);
+    if status == ExitStatus::Clean {
+        log::info!("✅ {} exits successfully! 🎉", state.info.app);
+    } else {
+        log::warn!("🟠 {} exits with status {status:?} ({})", state.info.app, status.code());
+    }
 
-    Ok(())
+    Ok(status)
 }
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::sleep;
+
+use crate::core::stats::Counter;
+use crate::core::{Command, Job};
+use crate::ctx::options::CheckpointStore;
+use crate::ctx::{SharedState, State};
+use crate::svc::pubsub;
+use crate::{decrement, increment};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("redis error: {0}")]
+    Redis(#[from] pubsub::Error)
+}
+
+/// Serializable part of a [`Job`], the span is recreated on resume.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    event: String,
+    channel: String,
+    data: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    #[serde(default = "first_attempt")]
+    attempt: u32
+}
+
+/// Pause between checks of a full command buffer while resuming.
+const RESUME_BACKOFF: Duration = Duration::from_millis(50);
+
+fn first_attempt() -> u32 {
+    1
+}
+
+impl From<Job> for Entry {
+    fn from(job: Job) -> Self {
+        Self {
+            id: job.id,
+            event: job.event,
+            channel: job.channel,
+            data: job.data,
+            timeout_ms: job.timeout.map(|timeout| timeout.as_millis() as u64),
+            attempt: job.attempt
+        }
+    }
+}
+
+impl From<Entry> for Job {
+    fn from(entry: Entry) -> Self {
+        let span = tracing::info_span!(
+            "message",
+            channel = entry.channel,
+            event = entry.event,
+            message_id = entry.id,
+            attempt = entry.attempt + 1
+        );
+        Self {
+            id: entry.id,
+            event: entry.event,
+            channel: entry.channel,
+            data: entry.data,
+            timeout: entry.timeout_ms.map(Duration::from_millis),
+            attempt: entry.attempt + 1,
+            rate_token: false,
+            span
+        }
+    }
+}
+
+/// Persist jobs canceled by a forced shutdown to the configured store.
+pub async fn save(state: &SharedState) {
+    let Some(store) = state.options.checkpoint else {
+        return;
+    };
+
+    let entries: Vec<Entry> = state.take_canceled_jobs().into_iter().map(Entry::from).collect();
+    if entries.is_empty() {
+        return;
+    }
+
+    match write(state, store, &entries).await {
+        Ok(()) => log::warn!("💾 {} canceled job(s) checkpointed for next start", entries.len()),
+        Err(e) => log::error!("⚠️  Checkpoint of {} canceled job(s) failed: {e}", entries.len())
+    }
+}
+
+/// Re-enqueue the jobs checkpointed by the previous run of this instance,
+/// once the dispatcher is listening. Only the jobs handed to the dispatcher
+/// are taken out of the store, the rest is kept for the next start.
+pub async fn resume(state: SharedState) {
+    let Some(store) = state.options.checkpoint else {
+        return;
+    };
+
+    tokio::select! {
+        _ = state.on_subscribed() => (),
+        _ = state.on_shutdown() => return
+    }
+
+    let entries = match read(&state, store).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("⚠️  Checkpoint could not be restored: {e}");
+            return;
+        }
+    };
+
+    if entries.is_empty() {
+        return;
+    }
+
+    let total = entries.len();
+    log::warn!("♻️  Resuming {total} checkpointed job(s)");
+
+    let mut resumed = 0;
+    for entry in entries {
+        if !enqueue(&state, entry.into()).await {
+            break;
+        }
+        resumed += 1;
+    }
+
+    if resumed < total {
+        log::warn!("💾 {} checkpointed job(s) kept for next start", total - resumed);
+    }
+    if let Err(e) = consume(&state, store, resumed).await {
+        log::error!("⚠️  {resumed} resumed job(s) could not be cleared from the checkpoint: {e}");
+    }
+}
+
+/// Hand a job to the dispatcher once the command buffer has room, `false`
+/// once shutting down.
+async fn enqueue(
+    state: &State,
+    mut job: Job
+) -> bool {
+    loop {
+        if state.is_shutting_down() {
+            return false;
+        }
+
+        increment!(state.stats, Counter::Resumed);
+        match state.broadcast.try_send(Command::Run(job)) {
+            Ok(()) => return true,
+            Err(command) => {
+                decrement!(state.stats, Counter::Resumed);
+                let Command::Run(back) = *command;
+                job = back;
+            }
+        }
+
+        tokio::select! {
+            _ = state.on_shutdown() => return false,
+            _ = sleep(RESUME_BACKOFF) => ()
+        }
+    }
+}
+
+fn redis_key(state: &State) -> String {
+    format!("{}:checkpoint:{}", state.options.registry_prefix, state.info.instance_id())
+}
+
+async fn write(
+    state: &State,
+    store: CheckpointStore,
+    entries: &[Entry]
+) -> Result<(), Error> {
+    match store {
+        CheckpointStore::File => {
+            // Append like the redis list does, entries not yet resumed are kept
+            let path = &state.options.checkpoint_path;
+            let existing: Vec<Entry> = match tokio::fs::read(path).await {
+                Ok(content) => serde_json::from_slice(&content)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into())
+            };
+            let all: Vec<&Entry> = existing.iter().chain(entries).collect();
+            tokio::fs::write(path, serde_json::to_vec(&all)?).await?;
+        }
+        CheckpointStore::Redis => {
+            let payloads =
+                entries.iter().map(serde_json::to_string).collect::<Result<Vec<_>, _>>()?;
+            let mut connection = state.redis_connection().await?;
+            let _: () =
+                connection.rpush(redis_key(state), payloads).await.map_err(pubsub::Error::from)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load the checkpoint, entries stay stored until [`consume`]d.
+async fn read(
+    state: &State,
+    store: CheckpointStore
+) -> Result<Vec<Entry>, Error> {
+    match store {
+        CheckpointStore::File => {
+            let path = &state.options.checkpoint_path;
+            let content = match tokio::fs::read(path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into())
+            };
+            Ok(serde_json::from_slice(&content)?)
+        }
+        CheckpointStore::Redis => {
+            let mut connection = state.redis_connection().await?;
+            let payloads: Vec<String> =
+                connection.lrange(redis_key(state), 0, -1).await.map_err(pubsub::Error::from)?;
+            payloads.iter().map(|p| Ok(serde_json::from_str(p)?)).collect()
+        }
+    }
+}
+
+/// Remove the first `count` entries, the ones resumed. Entries appended
+/// since the checkpoint was read are kept.
+async fn consume(
+    state: &State,
+    store: CheckpointStore,
+    count: usize
+) -> Result<(), Error> {
+    if count == 0 {
+        return Ok(());
+    }
+
+    match store {
+        CheckpointStore::File => {
+            let path = &state.options.checkpoint_path;
+            let entries: Vec<Entry> = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+            if entries.len() <= count {
+                tokio::fs::remove_file(path).await?;
+            } else {
+                tokio::fs::write(path, serde_json::to_vec(&entries[count..])?).await?;
+            }
+        }
+        CheckpointStore::Redis => {
+            let mut connection = state.redis_connection().await?;
+            let _: () = connection
+                .ltrim(redis_key(state), count as isize, -1)
+                .await
+                .map_err(pubsub::Error::from)?;
+        }
+    }
+    Ok(())
+}
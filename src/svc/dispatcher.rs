@@ -1,18 +1,24 @@
 use std::time::Duration;
 
 use rand::Rng;
+use serde_json::json;
 use tokio::time;
-use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
 
-use crate::core::handle::{Error as HandleError, Handle, Watcher};
-use crate::core::stats::{Counter, STATS};
+use crate::core::handle::{Error as HandleError, Handle};
+use crate::core::ratelimit::RatePolicy;
+use crate::core::report::TaskRecord;
+use crate::core::stats::Counter;
+use crate::core::{CancelReason, Command, Job, JobContext};
 use crate::ctx::SharedState;
 use crate::{decrement, increment};
 
 enum TaskResult {
     Success,
-    Canceled,
+    Canceled(CancelReason),
     Delayed,
+    TimedOut(Duration),
     Failed(TaskError)
 }
 
@@ -30,20 +36,29 @@ pub enum Error {
     UnknownTasks(usize)
 }
 
+/// Upper bound for collecting task results once every watcher is released.
+const RESULTS_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub async fn run(state: SharedState) -> crate::Result {
-    let handle =
-        create_handle(state.shutdown_token(), state.options.workers, state.options.grace_timeout);
+    let handle = watch_handle(state.clone());
+    tokio::spawn(release_parked(state.clone()));
     let mut receiver_tx = state.broadcast.subscribe();
     let mut task_id: u32 = 0;
+    let results = TaskTracker::new();
 
     loop {
         tokio::select! {
             _ = state.on_shutdown() => {
                 log::warn!("🔸 Dispatcher got shutdown signal ");
-                log::debug!("📉 Unhandled count at shutdown: {}", STATS.unhandled_count());
+                log::debug!("📉 Unhandled count at shutdown: {}", state.stats.unhandled_count());
+
+                for job in state.parked.take_all() {
+                    log::trace!("🔥 Parked job `{}` rejected during shutdown.", job.id);
+                    reject(&state, job);
+                }
 
                 loop {
-                    let unhandled = STATS.unhandled_count();
+                    let unhandled = state.stats.unhandled_count();
 
                     if unhandled == 0 {
                         break;
@@ -52,16 +67,16 @@ pub async fn run(state: SharedState) -> crate::Result {
                     }
 
                     match receiver_tx.recv().await {
-                        Ok(command) => {
-                            increment!(Counter::Rejected);
-                            log::trace!("🔥 Command `{:?}` rejected during shutdown.", command);
+                        Ok(Command::Run(job)) => {
+                            log::trace!("🔥 Job `{}` rejected during shutdown.", job.id);
+                            reject(&state, job);
                         }
                         Err(tokio::sync::broadcast::error::RecvError::Closed) => {
                             log::error!("📴 Channel closed, no more commands to process.");
                             break;
                         }
                         Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
-                            increment!(Counter::Lagged);
+                            increment!(state.stats, Counter::Lagged);
                             log::error!("⚠️  ‼️  Broadcast lagged, skipping termination");
                         }
                     }
@@ -73,83 +88,154 @@ pub async fn run(state: SharedState) -> crate::Result {
 
             result = receiver_tx.recv() => match result {
                 Ok(command) => {
-                    log::debug!("📩 Received command: {:?}", command);
+                    let span = command.span().clone();
+                    span.in_scope(|| log::debug!("📩 Received command: {:?}", command));
+
+                    let Command::Run(job) = &command;
+                    let job = job.clone();
+                    let labels = job.labels();
+
+                    let Some(pass) = state.breakers.allow(&job.event) else {
+                        span.in_scope(|| short_circuit(&state, job));
+                        continue;
+                    };
+
+                    if !throttle(&state, &job) {
+                        state.breakers.record(&job.event, pass, None);
+                        continue;
+                    }
 
-                    increment!(Counter::Waiting);
+                    increment!(state.stats, Counter::Waiting);
 
-                    let watcher = match handle.try_acquire_watcher().await {
+                    let acquired = match state.options.acquire_timeout {
+                        // Commands buffered behind this job are waiting as well
+                        Some(timeout) => handle.try_acquire_for(state.broadcast.len(), timeout).await,
+                        None => handle.try_acquire_watcher(state.broadcast.len()).await
+                    };
+
+                    let watcher = match acquired {
                         Ok(w) => {
-                            decrement!(Counter::Waiting);
-                            increment!(Counter::Accepted);
+                            decrement!(state.stats, Counter::Waiting);
+                            increment!(state.stats, Counter::Accepted, &labels);
                             w
                         }
                         Err(HandleError::ShuttingDown) => {
-                            decrement!(Counter::Waiting);
-                            increment!(Counter::Rejected);
-                            log::debug!("🔥 Shutdown initiated — job is not permitted");
+                            decrement!(state.stats, Counter::Waiting);
+                            state.breakers.record(&job.event, pass, None);
+                            span.in_scope(|| log::debug!("🔥 Shutdown initiated — job is not permitted"));
+                            reject(&state, job);
+                            continue;
+                        }
+                        Err(err) => {
+                            decrement!(state.stats, Counter::Waiting);
+                            state.breakers.record(&job.event, pass, None);
+                            span.in_scope(|| log::warn!("⛔ Job rejected: {err}"));
+                            reject(&state, job);
                             continue;
                         }
                     };
 
                     task_id += 1;
 
-                    log::debug!("🔹 Task #{} acquired permit. {} running ", task_id, handle.count());
+                    let deadline = job.timeout.or_else(|| state.options.task_timeout(&job.event));
+                    let record = TaskRecord { task_id, event: job.event.clone(), message_id: job.id.clone() };
 
-                    let state = state.clone();
-                    let task = tokio::spawn(async move {
-                        increment!(Counter::Running);
-                        run_job(task_id, state, watcher).await
+                    let task_span = tracing::info_span!(parent: &span, "task", task_id);
+                    task_span.in_scope(|| {
+                        tracing::debug!(task_id, "🔹 Task #{} acquired permit. {} running ", task_id, handle.count())
                     });
 
+                    let task_state = state.clone();
+                    let context = JobContext::new(task_id, &job, watcher, state.clone());
+                    let task = tokio::spawn(async move {
+                        increment!(task_state.stats, Counter::Running);
+                        match deadline {
+                            Some(deadline) => time::timeout(deadline, run_job(context, task_state))
+                                .await
+                                .unwrap_or(TaskResult::TimedOut(deadline)),
+                            None => run_job(context, task_state).await
+                        }
+                    }.instrument(task_span.clone()));
+
                     let started_at = time::Instant::now();
+                    let report_state = state.clone();
+                    let limiter = handle.clone();
                     // let handle_clone = handle.clone();
-                    tokio::spawn(async move {
+                    results.spawn(async move {
                         let task_result = match task.await {
                             Ok(inner) => inner,
                             Err(err) => {
-                                decrement!(Counter::Running);
-                                increment!(Counter::Failed);
-                                log::error!("⚠️  ‼️  Task spawn error for Task#{task_id} elapsed time: {err}");
+                                decrement!(report_state.stats, Counter::Running);
+                                increment!(report_state.stats, Counter::Failed, &labels);
+                                tracing::error!(task_id, "⚠️  ‼️  Task spawn error for Task#{task_id} elapsed time: {err}");
                                 return;
                             }
                         };
-                        decrement!(Counter::Running);
+                        decrement!(report_state.stats, Counter::Running);
                         let job_result = task_result;
                         let elapsed = started_at.elapsed();
+                        let elapsed_ms = elapsed.as_millis() as u64;
                         match job_result {
                             TaskResult::Success => {
-                                STATS.increment(Counter::Done);
-                                log::info!("❎ Task #{task_id} successfully done, elapsed: {:.2?}", elapsed);
+                                increment!(report_state.stats, Counter::Done, &labels);
+                                limiter.record(elapsed, false);
+                                report_state.breakers.record(&job.event, pass, Some(true));
+                                tracing::info!(task_id, elapsed_ms, "❎ Task #{task_id} successfully done, elapsed: {:.2?}", elapsed);
                             }
                             TaskResult::Delayed => {
-                                STATS.increment(Counter::Delayed);
-                                log::warn!("🟡 Task #{task_id} pushed to queue runner: elapsed: {:.2?}", elapsed);
+                                increment!(report_state.stats, Counter::Delayed, &labels);
+                                report_state.breakers.record(&job.event, pass, None);
+                                report_state.report.delayed(record);
+                                tracing::warn!(task_id, elapsed_ms, "🟡 Task #{task_id} pushed to queue runner: elapsed: {:.2?}", elapsed);
+                            }
+                            TaskResult::Canceled(CancelReason::Request) => {
+                                increment!(report_state.stats, Counter::Aborted, &labels);
+                                report_state.breakers.record(&job.event, pass, None);
+                                tracing::warn!(task_id, elapsed_ms, "📛 Task #{task_id} canceled on request, elapsed: {:.2?}", elapsed);
                             }
-                            TaskResult::Canceled => {
-                                STATS.increment(Counter::Canceled);
-                                log::error!(
+                            TaskResult::Canceled(CancelReason::Shutdown) => {
+                                increment!(report_state.stats, Counter::Canceled, &labels);
+                                report_state.breakers.record(&job.event, pass, None);
+                                report_state.report.canceled(record);
+                                report_state.job_canceled(job);
+                                tracing::error!(
+                                    task_id,
+                                    elapsed_ms,
                                     "📛 Task #{task_id} canceled due to shutdown forced, elapsed: {:.2?}",
                                     elapsed
                                 );
                             }
+                            TaskResult::TimedOut(deadline) => {
+                                increment!(report_state.stats, Counter::TimedOut, &labels);
+                                limiter.record(elapsed, true);
+                                report_state.breakers.record(&job.event, pass, Some(false));
+                                tracing::error!(
+                                    task_id,
+                                    elapsed_ms,
+                                    "⌛ Task #{task_id} timed out after its {} deadline",
+                                    humantime::format_duration(deadline)
+                                );
+                            }
                             TaskResult::Failed(err) => {
-                                STATS.increment(Counter::Failed);
-                                log::error!("❌ Task #{task_id} failed, elapsed: {:.2?} {err:?}", elapsed);
+                                increment!(report_state.stats, Counter::Failed, &labels);
+                                limiter.record(elapsed, true);
+                                report_state.breakers.record(&job.event, pass, Some(false));
+                                tracing::error!(task_id, elapsed_ms, "❌ Task #{task_id} failed, elapsed: {:.2?} {err:?}", elapsed);
                             }
                         }
                         // log::info!("Waiting tasks {}",handle_clone.count());
-                    });
+                    }.instrument(task_span));
                 }
                 Err(err) => match err {
                     tokio::sync::broadcast::error::RecvError::Closed => {
                         log::warn!("📴 Channel closed, no more commands to process.");
-                        handle.graceful_shutdown(state.options.grace_timeout);
+                        handle.graceful_shutdown(state.grace_timeout());
                         break
                     }
                     tokio::sync::broadcast::error::RecvError::Lagged(_) => {
-                        increment!(Counter::Lagged);
+                        increment!(state.stats, Counter::Lagged);
                         log::error!("⚠️  ‼️  Broadcast lagged, skipping command");
-                        handle.graceful_shutdown(state.options.grace_timeout);
+                        handle.graceful_shutdown(state.grace_timeout());
                         break
                     }
                 },
@@ -163,20 +249,39 @@ pub async fn run(state: SharedState) -> crate::Result {
         handle.grace_period()
     );
 
-    handle.wait_all_done().await;
+    state.report.phase("dispatcher_stopped");
+
+    tokio::select! {
+        done = handle.wait_all_done() => if done {
+            state.report.phase("grace_expired");
+        },
+        _ = state.on_drain_aborted() => ()
+    }
 
     // Wait for canceled job results. because we work in instantaneous, we must wait
     // cancellation task result before service shutdown
-    tokio::task::yield_now().await;
+    results.close();
+    tokio::select! {
+        result = time::timeout(RESULTS_TIMEOUT, results.wait()) => if result.is_err() {
+            log::warn!("⚠️  {} task result(s) not accounted in time", results.len());
+        },
+        _ = state.on_drain_aborted() => ()
+    }
+    state.report.phase("drained");
 
-    log::info!("📊 Final stats: {}", *STATS);
+    log::info!("📊 Final stats: {}", state.stats);
+
+    if state.is_drain_aborted() {
+        log::warn!("💀 Drain aborted, {} task(s) left unaccounted", state.stats.unknown_count());
+        return Ok(());
+    }
 
-    let loss_count = STATS.unknown_count();
+    let loss_count = state.stats.unknown_count();
     if loss_count > 0 {
         return Err(Error::UnknownTasks(loss_count).into());
     }
 
-    let unhandled_count = STATS.unhandled_count();
+    let unhandled_count = state.stats.unhandled_count();
     if unhandled_count > 0 {
         return Err(Error::UnhandledCommands(unhandled_count).into());
     }
@@ -184,30 +289,147 @@ pub async fn run(state: SharedState) -> crate::Result {
     Ok(())
 }
 
-pub fn create_handle(
-    token: CancellationToken,
-    max_count: Option<usize>,
-    grace_timeout: Option<Duration>
-) -> Handle {
-    let handle = Handle::new(max_count);
-    let cloned_handle = handle.clone();
+/// Account a refused job, a resumed job was already taken out of the
+/// checkpoint and goes back to it.
+fn reject(
+    state: &SharedState,
+    job: Job
+) {
+    increment!(state.stats, Counter::Rejected, &job.labels());
+    if job.attempt > 1 {
+        state.job_canceled(job);
+    }
+}
+
+/// Apply the rate limit of the job's event, `false` when the job must not
+/// start now, it was then parked or accounted as delayed or rejected.
+fn throttle(
+    state: &SharedState,
+    job: &Job
+) -> bool {
+    if job.rate_token {
+        return true;
+    }
+
+    // Jobs parked earlier keep their turn
+    let queued =
+        state.options.rate_policy == RatePolicy::Wait && state.parked.is_parked(&job.event);
+    if !queued && state.rate_limits.try_take(&job.event).is_ok() {
+        return true;
+    }
+
+    let labels = job.labels();
+    increment!(state.stats, Counter::Throttled, &labels);
+
+    match state.options.rate_policy {
+        RatePolicy::Wait => {
+            match state.parked.park(job.clone()) {
+                Ok(()) => job.span.in_scope(|| {
+                    log::debug!("🚦 Rate limit of `{}` reached, job {} parked", job.event, job.id)
+                }),
+                Err(job) => {
+                    job.span.in_scope(|| {
+                        log::warn!(
+                            "🚦 Parked jobs of `{}` at capacity, job {} rejected",
+                            job.event,
+                            job.id
+                        )
+                    });
+                    reject(state, *job);
+                }
+            }
+            false
+        }
+        RatePolicy::Delay => {
+            increment!(state.stats, Counter::Accepted, &labels);
+            increment!(state.stats, Counter::Delayed, &labels);
+            job.span.in_scope(|| {
+                log::warn!("🚦 Rate limit of `{}` reached, job {} delayed", job.event, job.id)
+            });
+            false
+        }
+        RatePolicy::Drop => {
+            increment!(state.stats, Counter::Rejected, &labels);
+            job.span.in_scope(|| {
+                log::warn!("🚦 Rate limit of `{}` reached, job {} dropped", job.event, job.id)
+            });
+            false
+        }
+    }
+}
+
+/// Re-send parked jobs in order as their event gets tokens again, one task
+/// serves every event. Jobs go back through the command buffer and stay
+/// parked while it is full, the dispatcher rejects the rest on shutdown.
+async fn release_parked(state: SharedState) {
+    loop {
+        let next = state.parked.release(&state.rate_limits, |job| {
+            state.broadcast.try_send(Command::Run(job)).map_err(|command| {
+                let Command::Run(job) = *command;
+                Box::new(job)
+            })
+        });
+
+        tokio::select! {
+            _ = state.on_shutdown() => return,
+            _ = state.parked.on_parked() => (),
+            _ = time::sleep(next.unwrap_or(Duration::MAX)), if next.is_some() => ()
+        }
+    }
+}
+
+/// Hand a job whose circuit is open to the dead-letter channel, or leave it
+/// to the queue runner as delayed.
+fn short_circuit(
+    state: &SharedState,
+    job: Job
+) {
+    let labels = job.labels();
+    increment!(state.stats, Counter::Accepted, &labels);
+
+    let Some(channel) = state.options.dead_letter_channel.clone() else {
+        increment!(state.stats, Counter::Delayed, &labels);
+        log::warn!("🔌 Circuit `{}` open, job {} delayed", job.event, job.id);
+        return;
+    };
+
+    increment!(state.stats, Counter::DeadLettered, &labels);
+    log::warn!("🔌 Circuit `{}` open, job {} sent to {channel}", job.event, job.id);
+    let payload = json!({
+        "id": job.id,
+        "event": job.event,
+        "channel": job.channel,
+        "data": job.data,
+        "reason": "circuit open"
+    });
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = state.publish(&channel, &payload.to_string()).await {
+            log::error!("⚠️  Dead-letter publish on {channel} failed: {e}");
+        }
+    });
+}
+
+pub fn watch_handle(state: SharedState) -> Handle {
+    let handle = state.handle.clone();
+    let token = state.shutdown_token();
     tokio::spawn(async move {
         // Wait for the cancellation token to be triggered
         token.cancelled().await;
         // Log the shutdown message
         log::debug!("💥 Handle notified for graceful shutdown...");
-        // Perform graceful shutdown with the specified grace timeout
-        cloned_handle.graceful_shutdown(grace_timeout);
+        // Perform graceful shutdown with the grace timeout of this shutdown
+        state.handle.graceful_shutdown(state.grace_timeout());
     });
     handle
 }
 
 async fn run_job(
-    job_id: u32,
-    state: SharedState,
-    watcher: Watcher
+    context: JobContext,
+    state: SharedState
 ) -> TaskResult {
-    log::debug!("▶️  Task #{} started...", job_id);
+    let job_id = context.task_id();
+    tracing::debug!(task_id = job_id, "▶️  Task #{} started...", job_id);
     let max_random_from_idle_timeout = state
         .options
         .idle_timeout
@@ -216,17 +438,25 @@ async fn run_job(
         .min(u128::from(u32::MAX)) as u64;
     let random_ms = rand::rng().random_range(1..=max_random_from_idle_timeout);
     tokio::select! {
-        _ = watcher.wait_graceful_shutdown() => {
+        // An admin cancel wins over a shutdown happening at the same time
+        biased;
+        _ = context.aborted() => {
+            TaskResult::Canceled(CancelReason::Request)
+        }
+        _ = context.cancelled() => {
            log::debug!("🫡 Task #{} notified for shutdown...", job_id);
-             let max_random_from_grace_timeout =  2 *  state.options.grace_timeout.unwrap_or(Duration::from_secs(1)).as_millis().min(u128::from(u32::MAX)) as u64;
+           context.set_phase("draining");
+             let max_random_from_grace_timeout =  2 *  state.grace_timeout().unwrap_or(Duration::from_secs(1)).as_millis().min(u128::from(u32::MAX)) as u64;
              let random_ms = rand::rng().random_range(1..=max_random_from_grace_timeout);
             tokio::select! {
-                _ = watcher.wait_shutdown() => {TaskResult::Canceled}
+                biased;
+                _ = context.aborted() => {TaskResult::Canceled(CancelReason::Request)}
+                _ = context.forced() => {TaskResult::Canceled(CancelReason::Shutdown)}
                 _ = time::sleep(Duration::from_millis(random_ms)) => {TaskResult::Delayed}
             }
         }
-        _ = watcher.wait_shutdown() => {
-             TaskResult::Canceled
+        _ = context.forced() => {
+             TaskResult::Canceled(CancelReason::Shutdown)
         }
         _ = time::sleep(Duration::from_millis(random_ms)) => {
             if random_ms % 5 == 0 {
@@ -1,3 +1,6 @@
+pub mod checkpoint;
 pub mod dispatcher;
 pub(crate) mod pubsub;
+pub mod rolling;
 pub mod shutdown;
+pub mod watchdog;
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use redis::Msg;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::error::Error;
+use super::target::Target;
+use crate::core::report::ShutdownTrigger;
+use crate::ctx::SharedState;
+
+#[derive(Debug, Deserialize)]
+struct AdminRequest {
+    #[serde(default)]
+    id: Option<Value>,
+    command: String,
+    #[serde(flatten)]
+    target: Target
+}
+
+#[derive(Debug)]
+enum AdminCommand {
+    Ping,
+    Pause,
+    Resume,
+    Drain,
+    SetWorkers(usize),
+    DumpStats,
+    Tasks,
+    Cancel(u32)
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let command = match (parts.next(), parts.next()) {
+            (Some("ping"), None) => AdminCommand::Ping,
+            (Some("pause"), None) => AdminCommand::Pause,
+            (Some("resume"), None) => AdminCommand::Resume,
+            (Some("drain"), None) => AdminCommand::Drain,
+            (Some("dump-stats"), None) => AdminCommand::DumpStats,
+            (Some("tasks"), None) => AdminCommand::Tasks,
+            (Some("cancel"), Some(id)) => match id.parse() {
+                Ok(id) => AdminCommand::Cancel(id),
+                _ => return Err(format!("invalid task id: {id}"))
+            },
+            (Some("set-workers"), Some(n)) => match n.parse() {
+                Ok(n) if n > 0 => AdminCommand::SetWorkers(n),
+                _ => return Err(format!("invalid worker count: {n}"))
+            },
+            _ => return Err(format!("unknown command: {s}"))
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("unexpected arguments: {s}"));
+        }
+
+        Ok(command)
+    }
+}
+
+/// Handle a message from the admin channel and publish the reply.
+///
+/// Admin messages look like `{"id": "r1", "command": "set-workers 4",
+/// "services": ["subscriber"]}` and are matched against this instance the
+/// same way `env.shutdown` is, see [`Target`].
+pub async fn handle_admin_message(
+    state: SharedState,
+    msg: Msg
+) -> Result<(), Error> {
+    let payload: String = msg.get_payload()?;
+
+    let request: AdminRequest = match serde_json::from_str(&payload) {
+        Ok(request) => request,
+        Err(e) => {
+            log::warn!("Received invalid admin command: {payload}. Error: {e}");
+            return Ok(());
+        }
+    };
+
+    if !request.target.matches(&state.info) {
+        log::debug!("Admin command `{}` ignored, not targeting us", request.command);
+        return Ok(());
+    }
+
+    log::info!("🛠️  Admin command received: {}", request.command);
+
+    let request_id =
+        request.id.as_ref().map(|id| id.as_str().map_or(id.to_string(), str::to_string));
+    let result = request.command.parse().and_then(|command| execute(&state, command, request_id));
+
+    let mut reply = json!({
+        "id": request.id,
+        "command": request.command,
+        "app": state.info.my_name(),
+        "hostname": state.info.get_hostname(),
+        "instance": state.info.instance_id(),
+        "ok": result.is_ok()
+    });
+    match result {
+        Ok(value) => reply["result"] = value,
+        Err(e) => {
+            log::warn!("⚠️  Admin command `{}` failed: {e}", request.command);
+            reply["error"] = Value::String(e)
+        }
+    }
+
+    if let Some(channel) = state.options.admin_reply_channel() {
+        state.publish(&channel, &reply.to_string()).await?;
+    }
+
+    Ok(())
+}
+
+fn execute(
+    state: &SharedState,
+    command: AdminCommand,
+    request_id: Option<String>
+) -> Result<Value, String> {
+    let handle = &state.handle;
+
+    match command {
+        AdminCommand::Ping => Ok(json!("pong")),
+        AdminCommand::Pause => {
+            state.pause();
+            Ok(json!({ "paused": true }))
+        }
+        AdminCommand::Resume => {
+            state.resume();
+            Ok(json!({ "paused": false }))
+        }
+        AdminCommand::Drain => {
+            state.initiate_shutdown(ShutdownTrigger::Admin { request_id });
+            Ok(json!({ "draining": true }))
+        }
+        AdminCommand::SetWorkers(_) if handle.is_adaptive() => {
+            // The next window of the limiter would silently undo it
+            Err("worker limit is adaptive, restart without --adaptive to set it".to_string())
+        }
+        AdminCommand::SetWorkers(workers) => {
+            let previous = handle.max_count();
+            handle.set_max_count(Some(workers));
+            log::warn!("🔧 Workers changed by admin: {previous:?} -> {workers}");
+            Ok(json!({ "previous": previous, "workers": workers }))
+        }
+        AdminCommand::DumpStats => {
+            log::info!("📊 Stats: {}", state.stats);
+            Ok(json!({
+                "stats": serde_json::to_value(&state.stats).map_err(|e| e.to_string())?,
+                "running": handle.count(),
+                "waiting": handle.waiting(),
+                "circuits": state.breakers.states(),
+                "workers": handle.max_count(),
+                "paused": state.is_paused(),
+                "ready": state.is_ready()
+            }))
+        }
+        AdminCommand::Tasks => serde_json::to_value(handle.tasks()).map_err(|e| e.to_string()),
+        AdminCommand::Cancel(task_id) => {
+            if !handle.cancel_task(task_id) {
+                return Err(format!("task #{task_id} is not running"));
+            }
+            log::warn!("🛑 Task #{task_id} canceled by admin");
+            Ok(json!({ "canceled": task_id }))
+        }
+    }
+}
@@ -1,25 +1,30 @@
 use redis::Msg;
+use serde::Deserialize;
 use serde_json::Value;
 
 use super::error::Error;
-use crate::core::Command;
-use crate::core::stats::Counter;
-use crate::ctx::SharedState;
+use super::target::Target;
+use crate::core::report::ShutdownTrigger;
+use crate::core::stats::{Counter, Labels};
+use crate::core::{Command, Job};
+use crate::ctx::{SharedState, logging};
 use crate::increment;
+use crate::svc::shutdown::{self, ShutdownMode, ShutdownRequest};
 
 pub async fn handle_message(
     state: SharedState,
     msg: Msg
 ) -> Result<(), Error> {
-    increment!(Counter::Received);
+    increment!(state.stats, Counter::Received);
 
     let payload: String = msg.get_payload()?;
+    let channel = msg.get_channel_name();
 
     // Parse the payload as JSON
     let json: Value = match serde_json::from_str(&payload) {
         Ok(v) => {
             let compact = serde_json::to_string(&v)?;
-            log::trace!("Received message on channel {}: {}", msg.get_channel_name(), compact);
+            log::trace!("Received message on channel {}: {}", channel, compact);
             v
         }
         Err(e) => {
@@ -30,49 +35,93 @@ pub async fn handle_message(
 
     // Extract the event name
     let event_name = json["event"].as_str().unwrap_or("unknown");
+    let message_id = message_id(&json);
+    let labels = Labels::new(event_name, channel);
+
+    let span =
+        tracing::info_span!("message", channel, event = event_name, message_id = %message_id);
+    logging::continue_trace(&span, json["traceparent"].as_str(), json["tracestate"].as_str());
+    let _entered = span.enter();
 
     log::debug!("📥 Received message: {}", event_name);
 
     match event_name {
         "env.updated" => {
-            if let Some(_d) = json.get("data") {
-                let _ = state.send_command(Command::Run);
+            if let Some(data) = json.get("data") {
+                let timeout = match json["timeout"].as_str().map(humantime::parse_duration) {
+                    Some(Ok(timeout)) => Some(timeout),
+                    Some(Err(e)) => {
+                        log::error!("❓Received env.updated event with invalid timeout: {e}");
+                        increment!(state.stats, Counter::Rejected, &labels);
+                        return Ok(());
+                    }
+                    None => None
+                };
+                let job = Job {
+                    id: message_id,
+                    event: event_name.to_string(),
+                    channel: channel.to_string(),
+                    data: data.clone(),
+                    timeout,
+                    attempt: 1,
+                    rate_token: false,
+                    span: span.clone()
+                };
+                let _ = state.send_command(Command::Run(job));
             } else {
                 log::error!("❓Received version.updated event without data");
-                increment!(Counter::Rejected);
+                increment!(state.stats, Counter::Rejected, &labels);
             }
         }
         "env.shutdown" => {
             if let Some(data) = json.get("data") {
-                if let Some(services) = data.get("services").and_then(|a| a.as_array()) {
-                    let my_name = state.info.my_name();
-                    if services
-                        .iter()
-                        .any(|v| v.as_str() == Some("*") || v.as_str() == Some(my_name))
-                    {
-                        // state.send_command(Command::Shutdown)?;
-                        log::warn!("🔸 Received shutdown message targeting: {}", my_name);
-                        increment!(Counter::Accepted);
-                        increment!(Counter::Done);
-                        state.initiate_shutdown();
+                let target = Target::deserialize(data)?;
+                let request = ShutdownRequest::deserialize(data)?;
+                if target.is_empty() {
+                    log::error!("⚠️  Received version.shutdown event without target");
+                    increment!(state.stats, Counter::Rejected, &labels);
+                } else if !target.matches(&state.info) {
+                    let instance_id = state.info.instance_id();
+                    log::debug!("⚠️  Shutdown message ignored, not targeting: {}", instance_id);
+                    increment!(state.stats, Counter::Ignored, &labels);
+                } else if let Some(delay) = request.delay() {
+                    // state.send_command(Command::Shutdown)?;
+                    let instance_id = state.info.instance_id();
+                    log::warn!("🔸 Received shutdown message targeting: {}", instance_id);
+                    increment!(state.stats, Counter::Accepted, &labels);
+                    increment!(state.stats, Counter::Done, &labels);
+                    let trigger = ShutdownTrigger::Event { message_id };
+                    if delay.is_zero() && request.mode == ShutdownMode::Immediate {
+                        if let Some(grace) = request.grace {
+                            state.set_grace_timeout(grace);
+                        }
+                        state.initiate_shutdown(trigger);
                     } else {
-                        log::debug!("⚠️  Shutdown message ignored, not targeting: {}", my_name);
-                        increment!(Counter::Ignored);
+                        tokio::spawn(shutdown::execute(state.clone(), request, delay, trigger));
                     }
                 } else {
-                    log::error!("⚠️  Received version.shutdown event without services");
-                    increment!(Counter::Rejected);
+                    log::error!("⚠️  Received version.shutdown event with both `at` and `after`");
+                    increment!(state.stats, Counter::Rejected, &labels);
                 }
             } else {
                 log::error!("⚠️  Received version.shutdown event without data");
-                increment!(Counter::Rejected);
+                increment!(state.stats, Counter::Rejected, &labels);
             }
         }
         _ => {
             log::debug!("Received message with unknown event: {event_name}");
-            increment!(Counter::Ignored);
+            increment!(state.stats, Counter::Ignored, &labels);
         }
     }
 
     Ok(())
 }
+
+/// Use the publisher supplied `id` when present, otherwise generate one.
+fn message_id(json: &Value) -> String {
+    match &json["id"] {
+        Value::String(id) => id.clone(),
+        Value::Number(id) => id.to_string(),
+        _ => format!("{:016x}", rand::random::<u64>())
+    }
+}
@@ -1,6 +1,9 @@
+mod admin;
 mod error;
 mod messages;
+mod publisher;
 mod subscriber;
+mod target;
 
 pub(crate) use error::Error;
 
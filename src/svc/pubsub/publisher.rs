@@ -0,0 +1,24 @@
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+
+use super::error::Error;
+use crate::ctx::State;
+
+impl State {
+    /// Open a command connection next to the pub-sub subscription.
+    pub async fn redis_connection(&self) -> Result<MultiplexedConnection, Error> {
+        let client = redis::Client::open(self.options.redis_url.as_str())?;
+        Ok(client.get_multiplexed_async_connection().await?)
+    }
+
+    /// Publish `payload` on `channel` over a short lived connection.
+    pub async fn publish(
+        &self,
+        channel: &str,
+        payload: &str
+    ) -> Result<usize, Error> {
+        let mut connection = self.redis_connection().await?;
+        let receivers: usize = connection.publish(channel, payload).await?;
+        Ok(receivers)
+    }
+}
@@ -8,6 +8,7 @@ use super::error::Error;
 use crate::core::stats::Counter;
 use crate::ctx::SharedState;
 use crate::increment;
+use crate::svc::pubsub::admin::handle_admin_message;
 use crate::svc::pubsub::messages::handle_message;
 
 static RETRY_COUNTER: Lazy<AtomicU8> = Lazy::new(|| AtomicU8::new(0));
@@ -25,7 +26,10 @@ pub async fn run(state: SharedState) -> crate::Result {
             break;
         }
 
-        match subscribe_channel(state.clone()).await {
+        let result = subscribe_channel(state.clone()).await;
+        state.mark_disconnected();
+
+        match result {
             Ok(_) => {
                 log::debug!("❎ Subscription ended gracefully.");
                 break;
@@ -75,9 +79,16 @@ async fn subscribe_channel(state: SharedState) -> Result<(), Error> {
     let client = redis::Client::open(options.redis_url.as_str())?;
     let mut subscriber = client.get_async_pubsub().await?;
 
-    subscriber.subscribe(&options.channel).await?;
+    let mut channels = vec![options.channel.as_str()];
+    channels.extend(options.admin_channel.as_deref());
+
+    subscriber.subscribe(&channels).await?;
     RETRY_COUNTER.store(0, Ordering::SeqCst);
+    state.mark_subscribed();
     log::info!("Subscribed to channel '{}'", &options.channel);
+    if let Some(admin_channel) = &options.admin_channel {
+        log::info!("Subscribed to admin channel '{}'", admin_channel);
+    }
 
     let graceful_timeout = options.grace_timeout.unwrap_or(Duration::from_secs(1));
 
@@ -93,6 +104,18 @@ async fn subscribe_channel(state: SharedState) -> Result<(), Error> {
                 result = msg_stream.next() => {
 
                     match result {
+                        Some(msg) if options.admin_channel.as_deref() == Some(msg.get_channel_name()) => {
+                            let handle_result = tokio::time::timeout(
+                                graceful_timeout,
+                                handle_admin_message(state.clone(), msg),
+                            ).await;
+
+                            match handle_result {
+                                Ok(Ok(_)) => {},
+                                Ok(Err(e)) => log::error!("Error handling admin message: {e:?}"),
+                                Err(_) => log::error!("Admin message handling timed out after {:?}", graceful_timeout),
+                            }
+                        }
                         Some(msg) => {
                             let handle_result = tokio::time::timeout(
                                 graceful_timeout,
@@ -102,11 +125,11 @@ async fn subscribe_channel(state: SharedState) -> Result<(), Error> {
                             match handle_result {
                                 Ok(Ok(_)) => {},
                                 Ok(Err(e)) => {
-                                    increment!(Counter::Rejected);
+                                    increment!(state.stats, Counter::Rejected);
                                     log::error!("Error handling message: {e:?}");
                                 },
                                 Err(_) => {
-                                    increment!(Counter::Rejected);
+                                    increment!(state.stats, Counter::Rejected);
                                     log::error!("Message handling timed out after {:?}", graceful_timeout);
                                 },
                             }
@@ -124,7 +147,7 @@ async fn subscribe_channel(state: SharedState) -> Result<(), Error> {
 
     match result {
         Ok(_) => {
-            if let Err(e) = subscriber.unsubscribe(&options.channel).await {
+            if let Err(e) = subscriber.unsubscribe(&channels).await {
                 log::warn!("❌ Unsubscribe failed during graceful shutdown: {}", e);
             }
             log::info!("📴 Unsubscribed from channel '{}'", &options.channel);
@@ -132,7 +155,7 @@ async fn subscribe_channel(state: SharedState) -> Result<(), Error> {
         }
         Err(e @ Error::Connection(_) | e @ Error::Disconnected) => Err(e),
         Err(e) => {
-            if let Err(e) = subscriber.unsubscribe(&options.channel).await {
+            if let Err(e) = subscriber.unsubscribe(&channels).await {
                 log::warn!("Unsubscribe failed during graceful shutdown: {}", e);
             }
             log::error!("❌ Subscription loop exited with error: {}", e);
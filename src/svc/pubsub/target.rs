@@ -0,0 +1,52 @@
+use serde::Deserialize;
+
+use crate::ctx::Info;
+use crate::ctx::utils::glob_match;
+
+/// Selects the instances a control message applies to.
+///
+/// Every selector is a list of glob patterns, an instance is targeted when
+/// each non-empty selector has a matching pattern. A target without any
+/// selector matches nobody.
+#[derive(Debug, Default, Deserialize)]
+pub struct Target {
+    /// App name, `*` addresses every service
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub instances: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>
+}
+
+impl Target {
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+            && self.hosts.is_empty()
+            && self.instances.is_empty()
+            && self.tags.is_empty()
+    }
+
+    pub fn matches(
+        &self,
+        info: &Info
+    ) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        selects(&self.services, |p| glob_match(p, info.my_name()))
+            && selects(&self.hosts, |p| glob_match(p, info.get_hostname()))
+            && selects(&self.instances, |p| glob_match(p, info.instance_id()))
+            && selects(&self.tags, |p| info.tags.iter().any(|tag| glob_match(p, tag)))
+    }
+}
+
+fn selects(
+    patterns: &[String],
+    matches: impl Fn(&str) -> bool
+) -> bool {
+    patterns.is_empty() || patterns.iter().any(|p| matches(p))
+}
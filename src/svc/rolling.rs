@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Script};
+use serde_json::json;
+use tokio::time::sleep;
+
+use crate::core::report::ShutdownTrigger;
+use crate::ctx::{SharedState, State};
+use crate::svc::pubsub::Error;
+
+const HEARTBEAT: Duration = Duration::from_secs(5);
+const REGISTRATION_TTL: Duration = Duration::from_secs(15);
+const TURN_POLL: Duration = Duration::from_secs(1);
+
+/// Deletes the rolling restart turn only while it is still held by `ARGV[1]`.
+const RELEASE_TURN: &str = r"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('del', KEYS[1])
+end
+return 0
+";
+
+/// Keep this instance registered in Redis while it runs.
+///
+/// The instance counts as healthy after its first subscription, at that
+/// point a rolling restart turn it still holds from before the restart is
+/// released so the next instance can go.
+pub async fn register(state: SharedState) {
+    tokio::select! {
+        _ = state.on_subscribed() => (),
+        _ = state.on_shutdown() => return
+    }
+
+    let mut connection = None;
+    let mut turn_released = false;
+
+    loop {
+        match heartbeat(&state, &mut connection, !turn_released).await {
+            Ok(()) => turn_released = true,
+            Err(e) => {
+                connection = None;
+                log::warn!("⚠️  Instance registration failed: {e}");
+            }
+        }
+
+        tokio::select! {
+            _ = state.on_shutdown() => break,
+            _ = sleep(HEARTBEAT) => ()
+        }
+    }
+
+    if let Err(e) = deregister(&state).await {
+        log::warn!("⚠️  Instance deregistration failed: {e}");
+    }
+}
+
+/// Drain this instance once no other instance is in the middle of a
+/// rolling restart.
+///
+/// The turn is held for at most `rolling_max_delay`, or until this instance
+/// re-registers healthy after its restart.
+pub async fn shutdown_in_turn(
+    state: SharedState,
+    trigger: ShutdownTrigger
+) {
+    log::warn!("⏳ Rolling shutdown requested, waiting for our turn");
+
+    loop {
+        match take_turn(&state).await {
+            Ok(true) => {
+                log::warn!("🔁 Rolling restart turn acquired: {}", state.info.stable_id());
+                state.initiate_shutdown(trigger);
+                return;
+            }
+            Ok(false) => log::debug!("⏳ Another instance is restarting, waiting"),
+            Err(e) => log::warn!("⚠️  Rolling restart turn check failed: {e}")
+        }
+
+        tokio::select! {
+            _ = state.on_shutdown() => {
+                log::debug!("Shutdown already in progress, rolling turn abandoned");
+                return;
+            }
+            _ = sleep(TURN_POLL) => ()
+        }
+    }
+}
+
+fn instance_key(state: &State) -> String {
+    format!("{}:instances:{}", state.options.registry_prefix, state.info.instance_id())
+}
+
+fn turn_key(state: &State) -> String {
+    format!("{}:rolling", state.options.registry_prefix)
+}
+
+async fn heartbeat(
+    state: &State,
+    connection: &mut Option<MultiplexedConnection>,
+    release_turn: bool
+) -> Result<(), Error> {
+    let connection = match connection {
+        Some(connection) => connection,
+        None => connection.insert(state.redis_connection().await?)
+    };
+
+    let registration = json!({
+        "app": state.info.my_name(),
+        "hostname": state.info.get_hostname(),
+        "tags": state.info.tags
+    });
+    let _: () = connection
+        .pset_ex(instance_key(state), registration.to_string(), REGISTRATION_TTL.as_millis() as u64)
+        .await?;
+
+    if release_turn {
+        let released: i32 = Script::new(RELEASE_TURN)
+            .key(turn_key(state))
+            .arg(state.info.stable_id())
+            .invoke_async(connection)
+            .await?;
+
+        if released == 1 {
+            log::info!("🔁 Back online, rolling restart turn released");
+        }
+    }
+
+    Ok(())
+}
+
+async fn deregister(state: &State) -> Result<(), Error> {
+    let mut connection = state.redis_connection().await?;
+    let _: () = connection.del(instance_key(state)).await?;
+    Ok(())
+}
+
+async fn take_turn(state: &State) -> Result<bool, Error> {
+    let mut connection = state.redis_connection().await?;
+    let acquired: Option<String> = redis::cmd("SET")
+        .arg(turn_key(state))
+        // Held across the restart, the new process must recognize it
+        .arg(state.info.stable_id())
+        .arg("NX")
+        .arg("PX")
+        .arg(state.options.rolling_max_delay.as_millis() as u64)
+        .query_async(&mut connection)
+        .await?;
+    Ok(acquired.is_some())
+}
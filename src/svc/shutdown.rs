@@ -1,20 +1,31 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Deserializer, de};
 use tokio::signal::unix::{SignalKind, signal};
+use tokio::time::{sleep, timeout};
 
+use crate::core::report::ShutdownTrigger;
 use crate::ctx::SharedState;
+use crate::exit::ExitStatus;
+use crate::svc::rolling;
 
 pub async fn listen(state: SharedState) {
     let mut terminate_signal =
         signal(SignalKind::terminate()).expect("Failed to create terminate signal handler");
+    let mut pause_signal =
+        signal(SignalKind::user_defined2()).expect("Failed to create pause signal handler");
 
     loop {
         tokio::select! {
-            _ = tokio::signal::ctrl_c() => {
-                log::debug!("🔥 Ctrl-C received, initiating shutdown");
-                state.initiate_shutdown();
-            }
-            _ = terminate_signal.recv() => {
-                log::debug!("🔥 Terminate signal received, initiating shutdown");
-                state.initiate_shutdown();
+            _ = tokio::signal::ctrl_c() => escalate(&state, "Ctrl-C"),
+            _ = terminate_signal.recv() => escalate(&state, "Terminate signal"),
+            _ = pause_signal.recv() => {
+                log::debug!("🔥 SIGUSR2 received, toggling pause");
+                if state.is_paused() {
+                    state.resume();
+                } else {
+                    state.pause();
+                }
             }
             // _ = state.on_shutdown() => {
             //     log::info!("❎ Shutdown completed");
@@ -23,3 +34,146 @@ pub async fn listen(state: SharedState) {
         }
     }
 }
+
+/// Each repeated signal moves the shutdown one phase further: graceful drain,
+/// then forced cancellation of running tasks, then an aborted drain. The
+/// exit status is left to `main` so the checkpoint and report are still
+/// written.
+fn escalate(
+    state: &SharedState,
+    signal: &str
+) {
+    if !state.is_shutting_down() {
+        log::debug!("🔥 {signal} received, initiating shutdown");
+        state.initiate_shutdown(ShutdownTrigger::Signal { name: signal.to_string() });
+    } else if state.force_shutdown() {
+        log::warn!("🔥 {signal} received again, skipping grace period and canceling running tasks");
+    } else {
+        log::error!("💀 {signal} received a third time, aborting the drain");
+        state.abort_drain();
+    }
+}
+
+/// Shutdown options carried by an `env.shutdown` event.
+///
+/// `at` (RFC 3339) or `after` (humantime, e.g. `10m`) schedule the drain,
+/// `grace` overrides `Options::grace_timeout` for this shutdown only.
+#[derive(Debug, Default, Deserialize)]
+pub struct ShutdownRequest {
+    #[serde(default, deserialize_with = "deserialize_rfc3339")]
+    pub at: Option<SystemTime>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub after: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub grace: Option<Duration>,
+    #[serde(default)]
+    pub mode: ShutdownMode
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownMode {
+    #[default]
+    Immediate,
+    /// One instance at a time, see [`rolling::shutdown_in_turn`]
+    Rolling
+}
+
+impl ShutdownRequest {
+    /// Time to wait before draining, `None` when both `at` and `after` are set.
+    pub fn delay(&self) -> Option<Duration> {
+        match (self.at, self.after) {
+            (Some(_), Some(_)) => None,
+            (Some(at), None) => Some(at.duration_since(SystemTime::now()).unwrap_or_default()),
+            (None, after) => Some(after.unwrap_or_default())
+        }
+    }
+}
+
+/// Carry out a shutdown request, waiting for its schedule first.
+pub async fn execute(
+    state: SharedState,
+    request: ShutdownRequest,
+    delay: Duration,
+    trigger: ShutdownTrigger
+) {
+    if !delay.is_zero() {
+        log::warn!("🕒 Shutdown scheduled in {}", humantime::format_duration(delay));
+
+        tokio::select! {
+            _ = state.on_shutdown() => {
+                log::debug!("Shutdown already in progress, schedule dropped");
+                return;
+            }
+            _ = sleep(delay) => ()
+        }
+    }
+
+    if let Some(grace) = request.grace {
+        state.set_grace_timeout(grace);
+    }
+
+    match request.mode {
+        ShutdownMode::Immediate => state.initiate_shutdown(trigger),
+        ShutdownMode::Rolling => rolling::shutdown_in_turn(state, trigger).await
+    }
+}
+
+fn deserialize_rfc3339<'de, D>(deserializer: D) -> Result<Option<SystemTime>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| humantime::parse_rfc3339_weak(&s).map_err(de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| humantime::parse_duration(&s).map_err(de::Error::custom))
+        .transpose()
+}
+
+/// Upper bound for publishing the shutdown report, Redis may be the reason
+/// we are going down.
+const REPORT_PUBLISH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Close the shutdown report and deliver it to the configured file and
+/// channel, delivery failures are logged only.
+pub async fn report(
+    state: &SharedState,
+    status: ExitStatus,
+    error: Option<String>
+) {
+    let options = &state.options;
+    if options.report_path.is_none() && options.report_channel.is_none() {
+        return;
+    }
+
+    let report = state.report.finish(&state.info, &state.stats, status.code(), error);
+    let payload = match serde_json::to_string_pretty(&report) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::error!("⚠️  Shutdown report serialization failed: {e}");
+            return;
+        }
+    };
+
+    if let Some(path) = &options.report_path {
+        match tokio::fs::write(path, &payload).await {
+            Ok(()) => log::info!("🧾 Shutdown report written to {}", path.display()),
+            Err(e) => log::error!("⚠️  Shutdown report write to {} failed: {e}", path.display())
+        }
+    }
+
+    if let Some(channel) = &options.report_channel {
+        match timeout(REPORT_PUBLISH_TIMEOUT, state.publish(channel, &payload)).await {
+            Ok(Ok(_)) => log::info!("🧾 Shutdown report published on {channel}"),
+            Ok(Err(e)) => log::error!("⚠️  Shutdown report publish on {channel} failed: {e}"),
+            Err(_) => log::error!("⚠️  Shutdown report publish on {channel} timed out")
+        }
+    }
+}
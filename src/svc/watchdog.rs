@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use tokio::time::interval;
+
+use crate::core::stats::Counter;
+use crate::ctx::SharedState;
+use crate::get;
+
+/// Status refresh interval when the unit has no watchdog.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Report status to systemd and ping its watchdog while the subscription is
+/// up, a subscriber stuck reconnecting stops the pings and lets systemd
+/// restart the unit. Runs until the process exits.
+pub async fn run(state: SharedState) {
+    let systemd = &state.systemd;
+    if !systemd.is_enabled() {
+        return;
+    }
+
+    let watchdog = systemd.watchdog_interval();
+    let mut ticker = interval(watchdog.map_or(STATUS_INTERVAL, |w| w.min(STATUS_INTERVAL)));
+
+    loop {
+        ticker.tick().await;
+
+        // Keep the watchdog fed while draining, the grace period may outlast it
+        if watchdog.is_some() && (state.is_connected() || state.is_shutting_down()) {
+            systemd.watchdog();
+        }
+        systemd.status(&status(&state));
+    }
+}
+
+fn status(state: &SharedState) -> String {
+    let phase = if state.is_shutting_down() {
+        "draining"
+    } else if !state.is_connected() {
+        "connecting"
+    } else if state.is_paused() {
+        "paused"
+    } else {
+        "ready"
+    };
+    format!(
+        "{phase}: {} running, {} waiting, {} done, {} failed",
+        get!(state.stats, Counter::Running),
+        get!(state.stats, Counter::Waiting),
+        get!(state.stats, Counter::Done),
+        get!(state.stats, Counter::Failed)
+    )
+}
@@ -0,0 +1,36 @@
+#!/usr/bin/env bash
+
+# Usage: send_admin.sh <command> [args...]
+#   send_admin.sh ping
+#   send_admin.sh pause
+#   send_admin.sh resume
+#   send_admin.sh set-workers 4
+#   send_admin.sh tasks
+#   send_admin.sh cancel 12
+#   send_admin.sh dump-stats
+#   send_admin.sh drain
+#
+# Replies are published on "$CHANNEL_NAME.replies", watch them with:
+#   redis-cli SUBSCRIBE admin-channel.replies
+
+CHANNEL_NAME="${CHANNEL_NAME:-admin-channel}"
+SERVICES="${SERVICES:-subscriber}"
+
+if [ $# -eq 0 ]; then
+	echo "usage: $0 <command> [args...]" >&2
+	exit 1
+fi
+
+# Construct the JSON payload
+payload=$(
+	cat << EOF
+{
+  "id": "$(date +%s%N)",
+  "command": "$*",
+  "services": ["$SERVICES"]
+}
+EOF
+)
+
+# Publish the command to the Redis admin channel
+redis-cli PUBLISH "$CHANNEL_NAME" "$payload"